@@ -0,0 +1,141 @@
+use crate::filetype::{self, FileType};
+use std::ops::Range;
+
+/** 查找匹配括号时最多向前后扫描的行数 */
+const SCAN_ROWS: usize = 1000;
+
+/** 标记一行中哪些字节是代码（不在字符串或注释中），in_block 为跨行的块注释状态 */
+pub fn code_mask(file_type: &FileType, line: &str, in_block: &mut bool) -> Vec<bool> {
+    let mut mask = vec![true; line.len()];
+    let mut quote: Option<char> = None;
+    let mut i = 0;
+    while i < line.len() {
+        let rest = &line[i..];
+        let len = rest.chars().next().map_or(1, char::len_utf8);
+        if *in_block {
+            let (_, end) = file_type.block_comment.unwrap();
+            let skip = if rest.starts_with(end) {
+                end.len()
+            } else {
+                len
+            };
+            *in_block = !rest.starts_with(end);
+            mask[i..i + skip].fill(false);
+            i += skip;
+            continue;
+        }
+        if let Some(q) = quote {
+            let skip = match rest.strip_prefix('\\') {
+                Some(escaped) => 1 + escaped.chars().next().map_or(0, char::len_utf8),
+                None => len,
+            };
+            if rest.starts_with(q) {
+                quote = None;
+            }
+            mask[i..i + skip].fill(false);
+            i += skip;
+            continue;
+        }
+        match file_type.block_comment {
+            Some((start, _)) if rest.starts_with(start) => {
+                *in_block = true;
+                mask[i..i + start.len()].fill(false);
+                i += start.len();
+                continue;
+            }
+            _ => {}
+        }
+        if !file_type.line_comment.is_empty() && rest.starts_with(file_type.line_comment) {
+            mask[i..].fill(false);
+            break;
+        }
+        let ch = rest.chars().next().unwrap();
+        if file_type.closing_pair(ch) == Some(ch) {
+            quote = Some(ch);
+            mask[i] = false;
+        } else if let Some(literal) = char_literal(rest) {
+            mask[i..i + literal].fill(false);
+            i += literal;
+            continue;
+        }
+        i += len;
+    }
+    mask
+}
+
+/** 'x' 或 '\n' 形式的字符字面量的长度，' 不作为引号配对的语言（如 Rust）中使用 */
+fn char_literal(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+    let (_, first) = chars.next().filter(|_| rest.starts_with('\''))?;
+    if first == '\\' {
+        chars.next()?;
+    }
+    chars
+        .next()
+        .filter(|&(_, c)| c == '\'')
+        .map(|(index, _)| index + 1)
+}
+
+/** 查找 at 处括号的匹配位置，只扫描 rows 范围内的行 */
+pub fn find_match<'a>(
+    file_type: &FileType,
+    rows: Range<usize>,
+    get_row: impl Fn(usize) -> &'a str,
+    at: (usize, usize),
+) -> Option<(usize, usize)> {
+    let (x, y) = at;
+    let ch = get_row(y)[x..].chars().next()?;
+    let (open, close, forward) =
+        match (filetype::closing_bracket(ch), filetype::opening_bracket(ch)) {
+            (Some(close), _) => (ch, close, true),
+            (_, Some(open)) => (open, ch, false),
+            _ => return None,
+        };
+    let first = rows.start.max(y.saturating_sub(SCAN_ROWS));
+    let last = rows.end.min(y + SCAN_ROWS + 1);
+    let mut in_block = false;
+    let masks: Vec<Vec<bool>> = (first..last)
+        .map(|row| code_mask(file_type, get_row(row), &mut in_block))
+        .collect();
+    if !masks[y - first][x] {
+        return None;
+    }
+    let mut depth = 0;
+    let mut visit = |row: usize, index: usize, c: char| {
+        if !masks[row - first][index] {
+            return false;
+        }
+        if c == open {
+            depth += if forward { 1 } else { -1 };
+        } else if c == close {
+            depth += if forward { -1 } else { 1 };
+        }
+        depth == 0
+    };
+    if forward {
+        for row in y..last {
+            let line = get_row(row);
+            let start = if row == y { x } else { 0 };
+            for (index, c) in line[start..].char_indices() {
+                if visit(row, start + index, c) {
+                    return Some((start + index, row));
+                }
+            }
+        }
+    } else {
+        for row in (first..=y).rev() {
+            let line = get_row(row);
+            let end = if row == y {
+                x + ch.len_utf8()
+            } else {
+                line.len()
+            };
+            for (index, c) in line[..end].char_indices().rev() {
+                if visit(row, index, c) {
+                    return Some((index, row));
+                }
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/** 目录列表中表示上一级目录的行 */
+pub const PARENT: &str = "../";
+
+/** 列出 dir 的内容：先是 ../，然后是以 / 结尾的子目录和文件，各自按名称排序 */
+pub fn list(dir: &Path) -> io::Result<Vec<String>> {
+    let (mut dirs, mut files) = (Vec::new(), Vec::new());
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // 指向目录的符号链接也当作目录
+        if entry.path().is_dir() {
+            dirs.push(name + "/");
+        } else {
+            files.push(name);
+        }
+    }
+    dirs.sort();
+    files.sort();
+    Ok(Some(PARENT.to_string())
+        .into_iter()
+        .chain(dirs)
+        .chain(files)
+        .collect())
+}
+
+/** 目录列表中某一行对应的路径 */
+pub fn entry_path(dir: &Path, entry: &str) -> PathBuf {
+    if entry == PARENT {
+        dir.parent().unwrap_or(dir).to_path_buf()
+    } else {
+        dir.join(entry.trim_end_matches('/'))
+    }
+}
+
+/**
+ * 补全路径的最后一段，补全到所有候选共同的前缀，只有一个候选且是目录时加上 /。
+ * 以 . 开头的文件只在输入的最后一段也以 . 开头时才作为候选
+ */
+pub fn complete(input: &str) -> String {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(index) => (&input[..=index], &input[index + 1..]),
+        None => ("", input),
+    };
+    let entries = match fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+        Ok(entries) => entries,
+        Err(_) => return input.into(),
+    };
+    let candidates: Vec<(String, bool)> = entries
+        .filter_map(Result::ok)
+        .map(|entry| {
+            (
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path().is_dir(),
+            )
+        })
+        .filter(|(name, _)| {
+            name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'))
+        })
+        .collect();
+    let completed = match &candidates[..] {
+        [] => return input.into(),
+        [(name, true)] => format!("{}/", name),
+        [(name, false)] => name.clone(),
+        [(first, _), rest @ ..] => rest.iter().fold(first.clone(), |common, (name, _)| {
+            common
+                .chars()
+                .zip(name.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect()
+        }),
+    };
+    format!("{}{}", dir, completed)
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
+/** 配置文件 ~/.poundrc，每行一个 "key = value"，# 开头为注释 */
+const CONFIG_FILE: &str = ".poundrc";
+
+pub struct Config {
+    /** 是否捕获鼠标事件 */
+    pub mouse: bool,
+    /** 换行时是否自动缩进 */
+    pub auto_indent: bool,
+    /** 无法从文件内容推断缩进方式时，Tab 是否插入空格 */
+    pub expand_tabs: bool,
+    /** 无法从文件内容推断缩进方式时的缩进宽度 */
+    pub indent_width: usize,
+    /** 输入左括号或引号时是否自动补全右半部分 */
+    pub auto_pair: bool,
+    /** 查找时是否只匹配完整的单词 */
+    pub whole_word: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mouse: true,
+            auto_indent: true,
+            expand_tabs: true,
+            indent_width: 4,
+            auto_pair: true,
+            whole_word: false,
+        }
+    }
+}
+
+impl Config {
+    /** 读取配置文件，文件不存在或某一行无法解析时使用默认值 */
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        let contents = match Self::path().and_then(|path| fs::read_to_string(path).ok()) {
+            Some(contents) => contents,
+            None => return config,
+        };
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                let _ = config.set(key.trim(), value.trim());
+            }
+        }
+        config
+    }
+
+    fn path() -> Option<PathBuf> {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(CONFIG_FILE))
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "mouse" => self.mouse = parse_bool(value)?,
+            "auto_indent" => self.auto_indent = parse_bool(value)?,
+            "whole_word" => self.whole_word = parse_bool(value)?,
+            "auto_pair" => self.auto_pair = parse_bool(value)?,
+            "expand_tabs" => self.expand_tabs = parse_bool(value)?,
+            "indent_width" => {
+                self.indent_width = match value.parse() {
+                    Ok(width @ 1..=16) => width,
+                    _ => return Err(format!("Expected a width from 1 to 16, got: {}", value)),
+                }
+            }
+            _ => return Err(format!("Unknown option: {}", key)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("Expected on/off, got: {}", value)),
+    }
+}
@@ -0,0 +1,465 @@
+use std::cmp;
+use std::ops::Range;
+
+/** 编辑距离超过该值时不再寻找最短的差异，把剩下的部分整个作为一处修改 */
+const MAX_EDIT_DISTANCE: usize = 1000;
+
+/** 一处修改：旧内容中的 old 被替换成了新内容中的 new，纯插入时 old 为空，纯删除时 new 为空 */
+#[derive(Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/** 用 Myers 算法比较两组行，返回按位置排列的修改 */
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    // 先去掉相同的开头和结尾，通常只剩下很少的几行需要比较
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+    let pairs = common_pairs(a, b).unwrap_or_default();
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (next_i, next_j) in pairs.into_iter().chain(Some((a.len(), b.len()))) {
+        if next_i > i || next_j > j {
+            hunks.push(Hunk {
+                old: prefix + i..prefix + next_i,
+                new: prefix + j..prefix + next_j,
+            });
+        }
+        (i, j) = (next_i + 1, next_j + 1);
+    }
+    hunks
+}
+
+/**
+ * 最短编辑序列中保持不变的 (a 中的下标, b 中的下标)，按顺序排列。
+ * 编辑距离超过 MAX_EDIT_DISTANCE 时返回 None
+ */
+fn common_pairs<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<(usize, usize)>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = cmp::min(a.len() + b.len(), MAX_EDIT_DISTANCE) as isize;
+    let offset = max + 1;
+    let index = |k: isize| (k + offset) as usize;
+    // v[k] 为对角线 k 上走得最远的 x，trace[d] 为第 d 步开始前 v 中对角线 -d-1..=d+1 的部分，
+    // 倒推时只会用到这些对角线，不必每步复制整个 v
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+    let mut found = false;
+    'search: for d in 0..=max {
+        trace.push(v[index(-d - 1)..=index(d + 1)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+        }
+    }
+    if !found {
+        return None;
+    }
+    // 从终点倒推回起点，沿途的对角线就是不变的部分
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let index = |k: isize| (k + d + 1) as usize;
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = v[index(previous_k)];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+        if d > 0 {
+            (x, y) = (previous_x, previous_y);
+        }
+    }
+    pairs.reverse();
+    Some(pairs)
+}
+
+/** 差异视图中的一行 */
+pub enum DiffRow {
+    /** 统一格式中每组修改前的 @@ -1,3 +1,4 @@ */
+    Header(String),
+    Context(String),
+    Removed(String),
+    Added(String),
+    /** 并排显示时左右两侧的行号（从 0 开始）和内容，一侧没有对应的行时为 None，修改过的行 changed 为 true */
+    Pair {
+        old: Option<(usize, String)>,
+        new: Option<(usize, String)>,
+        changed: bool,
+    },
+}
+
+/** 统一格式的差异，每组修改前后保留 context 行不变的内容。同时返回每组修改在结果中的位置和在新内容中的起始行 */
+pub fn unified(
+    old: &[&str],
+    new: &[&str],
+    hunks: &[Hunk],
+    context: usize,
+) -> (Vec<DiffRow>, Vec<(usize, usize)>) {
+    let mut rows = Vec::new();
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i < hunks.len() {
+        // 上下文有重叠的修改合并成一组
+        let mut j = i;
+        while j + 1 < hunks.len() && hunks[j + 1].old.start - hunks[j].old.end <= 2 * context {
+            j += 1;
+        }
+        let old_start = hunks[i].old.start.saturating_sub(context);
+        let old_end = cmp::min(hunks[j].old.end + context, old.len());
+        let new_start = hunks[i].new.start.saturating_sub(context);
+        let new_end = cmp::min(hunks[j].new.end + context, new.len());
+        starts.push((rows.len(), hunks[i].new.start));
+        rows.push(DiffRow::Header(format!(
+            "@@ -{},{} +{},{} @@",
+            old_start + 1,
+            old_end - old_start,
+            new_start + 1,
+            new_end - new_start
+        )));
+        let mut line = old_start;
+        for hunk in &hunks[i..=j] {
+            rows.extend(
+                old[line..hunk.old.start]
+                    .iter()
+                    .map(|it| DiffRow::Context(it.to_string())),
+            );
+            rows.extend(
+                old[hunk.old.clone()]
+                    .iter()
+                    .map(|it| DiffRow::Removed(it.to_string())),
+            );
+            rows.extend(
+                new[hunk.new.clone()]
+                    .iter()
+                    .map(|it| DiffRow::Added(it.to_string())),
+            );
+            line = hunk.old.end;
+        }
+        rows.extend(
+            old[line..old_end]
+                .iter()
+                .map(|it| DiffRow::Context(it.to_string())),
+        );
+        i = j + 1;
+    }
+    (rows, starts)
+}
+
+/** 并排显示完整的两个版本，修改过的行左右对齐。同时返回每处修改在结果中的位置和在新内容中的起始行 */
+pub fn side_by_side(
+    old: &[&str],
+    new: &[&str],
+    hunks: &[Hunk],
+) -> (Vec<DiffRow>, Vec<(usize, usize)>) {
+    let mut rows = Vec::new();
+    let mut starts = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let end = Hunk {
+        old: old.len()..old.len(),
+        new: new.len()..new.len(),
+    };
+    for hunk in hunks.iter().chain(Some(&end)) {
+        while i < hunk.old.start {
+            rows.push(DiffRow::Pair {
+                old: Some((i, old[i].to_string())),
+                new: Some((j, new[j].to_string())),
+                changed: false,
+            });
+            (i, j) = (i + 1, j + 1);
+        }
+        if hunk.old.is_empty() && hunk.new.is_empty() {
+            break;
+        }
+        starts.push((rows.len(), hunk.new.start));
+        for k in 0..cmp::max(hunk.old.len(), hunk.new.len()) {
+            let side = |range: &Range<usize>, lines: &[&str]| {
+                (k < range.len()).then(|| (range.start + k, lines[range.start + k].to_string()))
+            };
+            rows.push(DiffRow::Pair {
+                old: side(&hunk.old, old),
+                new: side(&hunk.new, new),
+                changed: true,
+            });
+        }
+        (i, j) = (hunk.old.end, hunk.new.end);
+    }
+    (rows, starts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(hunks: &[Hunk]) -> Vec<(Range<usize>, Range<usize>)> {
+        hunks
+            .iter()
+            .map(|it| (it.old.clone(), it.new.clone()))
+            .collect()
+    }
+
+    /** 把 hunks 应用到 old 上，结果应该与 new 相同 */
+    fn patch<T: Clone>(old: &[T], new: &[T], hunks: &[Hunk]) -> Vec<T> {
+        let mut result = Vec::new();
+        let mut i = 0;
+        for hunk in hunks {
+            result.extend_from_slice(&old[i..hunk.old.start]);
+            result.extend_from_slice(&new[hunk.new.clone()]);
+            i = hunk.old.end;
+        }
+        result.extend_from_slice(&old[i..]);
+        result
+    }
+
+    fn numbers(range: Range<usize>) -> Vec<String> {
+        range.map(|it| it.to_string()).collect()
+    }
+
+    #[test]
+    fn whole_file_added_or_removed() {
+        let empty: [&str; 0] = [];
+        assert!(diff(&empty, &empty).is_empty());
+        assert_eq!(ranges(&diff(&empty, &["a", "b"])), vec![(0..0, 0..2)]);
+        assert_eq!(ranges(&diff(&["a", "b"], &empty)), vec![(0..2, 0..0)]);
+    }
+
+    #[test]
+    fn diff_of_identical_lines_is_empty() {
+        assert!(diff(&["a", "b", "c"], &["a", "b", "c"]).is_empty());
+    }
+
+    #[test]
+    fn diff_finds_insertions_deletions_and_changes() {
+        assert_eq!(
+            ranges(&diff(&["a", "c"], &["a", "b", "c"])),
+            vec![(1..1, 1..2)]
+        );
+        assert_eq!(
+            ranges(&diff(&["a", "b", "c"], &["a", "c"])),
+            vec![(1..2, 1..1)]
+        );
+        assert_eq!(
+            ranges(&diff(
+                &["a", "b", "c", "d", "e"],
+                &["a", "x", "c", "e", "y"]
+            )),
+            vec![(1..2, 1..2), (3..4, 3..3), (5..5, 4..5)]
+        );
+    }
+
+    #[test]
+    fn common_pairs_is_a_shortest_edit() {
+        // Myers 论文中的例子，编辑距离为 5
+        let a: Vec<char> = "abcabba".chars().collect();
+        let b: Vec<char> = "cbabac".chars().collect();
+        let pairs = common_pairs(&a, &b).unwrap();
+        assert_eq!(pairs.len(), (a.len() + b.len() - 5) / 2);
+        assert!(pairs
+            .windows(2)
+            .all(|it| it[0].0 < it[1].0 && it[0].1 < it[1].1));
+        assert!(pairs.iter().all(|&(i, j)| a[i] == b[j]));
+        assert_eq!(patch(&a, &b, &diff(&a, &b)), b);
+    }
+
+    #[test]
+    fn common_pairs_gives_up_beyond_the_edit_distance_limit() {
+        let half = MAX_EDIT_DISTANCE / 2;
+        let within = common_pairs(&numbers(0..half), &numbers(half..2 * half));
+        assert_eq!(within, Some(vec![]));
+        let beyond = common_pairs(&numbers(0..half + 1), &numbers(half + 1..2 * half + 2));
+        assert_eq!(beyond, None);
+    }
+
+    #[test]
+    fn diff_beyond_the_limit_is_one_hunk() {
+        // 中间有一行相同，但编辑距离超过上限，相同的开头和结尾之间整个作为一处修改
+        let mut old = numbers(0..MAX_EDIT_DISTANCE);
+        let mut new = numbers(MAX_EDIT_DISTANCE..2 * MAX_EDIT_DISTANCE);
+        old[MAX_EDIT_DISTANCE / 2] = "same".into();
+        new[MAX_EDIT_DISTANCE / 2] = "same".into();
+        old.insert(0, "first".into());
+        new.insert(0, "first".into());
+        let hunks = diff(&old, &new);
+        let end = MAX_EDIT_DISTANCE + 1;
+        assert_eq!(ranges(&hunks), vec![(1..end, 1..end)]);
+        assert_eq!(patch(&old, &new, &hunks), new);
+    }
+
+    fn show(rows: &[DiffRow]) -> Vec<String> {
+        let side = |line: &Option<(usize, String)>| {
+            line.as_ref()
+                .map_or("-".to_string(), |(i, text)| format!("{}:{}", i, text))
+        };
+        rows.iter()
+            .map(|row| match row {
+                DiffRow::Header(header) => header.clone(),
+                DiffRow::Context(text) => format!(" {}", text),
+                DiffRow::Removed(text) => format!("-{}", text),
+                DiffRow::Added(text) => format!("+{}", text),
+                DiffRow::Pair { old, new, changed } => format!(
+                    "{}{} {}",
+                    if *changed { '*' } else { ' ' },
+                    side(old),
+                    side(new)
+                ),
+            })
+            .collect()
+    }
+
+    /** 1 到 10 共 10 行，把 changed 中的行（从 1 开始）改为 x 加行号 */
+    fn changed_lines(changed: &[usize]) -> (Vec<String>, Vec<String>) {
+        let old = numbers(1..11);
+        let new = old
+            .iter()
+            .map(|it| {
+                let line: usize = it.parse().unwrap();
+                if changed.contains(&line) {
+                    format!("x{}", line)
+                } else {
+                    it.clone()
+                }
+            })
+            .collect();
+        (old, new)
+    }
+
+    fn unified_of(
+        old: &[String],
+        new: &[String],
+        context: usize,
+    ) -> (Vec<String>, Vec<(usize, usize)>) {
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let new: Vec<&str> = new.iter().map(String::as_str).collect();
+        let (rows, starts) = unified(&old, &new, &diff(&old, &new), context);
+        (show(&rows), starts)
+    }
+
+    #[test]
+    fn unified_of_new_file() {
+        let (rows, starts) = unified_of(&[], &numbers(0..2), 3);
+        assert_eq!(rows, vec!["@@ -1,0 +1,2 @@", "+0", "+1"]);
+        assert_eq!(starts, vec![(0, 0)]);
+    }
+
+    #[test]
+    fn unified_clamps_context_to_the_file() {
+        let (old, new) = changed_lines(&[1, 10]);
+        let (rows, _) = unified_of(&old, &new, 1);
+        assert_eq!(
+            rows,
+            vec![
+                "@@ -1,2 +1,2 @@",
+                "-1",
+                "+x1",
+                " 2",
+                "@@ -9,2 +9,2 @@",
+                " 9",
+                "-10",
+                "+x10"
+            ]
+        );
+    }
+
+    #[test]
+    fn unified_merges_overlapping_context() {
+        let (old, new) = changed_lines(&[3, 7]);
+        let (rows, starts) = unified_of(&old, &new, 2);
+        assert_eq!(
+            rows,
+            vec![
+                "@@ -1,9 +1,9 @@",
+                " 1",
+                " 2",
+                "-3",
+                "+x3",
+                " 4",
+                " 5",
+                " 6",
+                "-7",
+                "+x7",
+                " 8",
+                " 9"
+            ]
+        );
+        assert_eq!(starts, vec![(0, 2)]);
+    }
+
+    #[test]
+    fn unified_keeps_separate_context_apart() {
+        let (old, new) = changed_lines(&[3, 7]);
+        let (rows, starts) = unified_of(&old, &new, 1);
+        assert_eq!(
+            rows,
+            vec![
+                "@@ -2,3 +2,3 @@",
+                " 2",
+                "-3",
+                "+x3",
+                " 4",
+                "@@ -6,3 +6,3 @@",
+                " 6",
+                "-7",
+                "+x7",
+                " 8"
+            ]
+        );
+        assert_eq!(starts, vec![(0, 2), (5, 6)]);
+    }
+
+    #[test]
+    fn unified_merges_context_that_just_touches() {
+        // 两处修改之间正好是 2 * context 行
+        let (old, new) = changed_lines(&[3, 6]);
+        let (rows, starts) = unified_of(&old, &new, 1);
+        assert_eq!(rows[0], "@@ -2,6 +2,6 @@");
+        assert_eq!(rows.iter().filter(|it| it.starts_with("@@")).count(), 1);
+        assert_eq!(starts, vec![(0, 2)]);
+    }
+
+    #[test]
+    fn side_by_side_aligns_changed_lines() {
+        let old = ["a", "b", "c", "d"];
+        let new = ["a", "x", "y", "c"];
+        let (rows, starts) = side_by_side(&old, &new, &diff(&old, &new));
+        assert_eq!(
+            show(&rows),
+            vec![" 0:a 0:a", "*1:b 1:x", "*- 2:y", " 2:c 3:c", "*3:d -"]
+        );
+        assert_eq!(starts, vec![(1, 1), (4, 4)]);
+    }
+
+    #[test]
+    fn side_by_side_of_deleted_file() {
+        let (rows, starts) = side_by_side(&["a"], &[], &diff(&["a"], &[]));
+        assert_eq!(show(&rows), vec!["*0:a -"]);
+        assert_eq!(starts, vec![(0, 0)]);
+    }
+}
@@ -0,0 +1,154 @@
+use encoding_rs::{GB18030, GBK, UTF_16BE, UTF_16LE};
+use std::io::{self, ErrorKind};
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Gbk,
+    Gb18030,
+}
+
+impl Encoding {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Some(Self::Utf8),
+            "utf-8-bom" | "utf8-bom" => Some(Self::Utf8Bom),
+            "utf-16le" | "utf16le" | "utf-16" => Some(Self::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Self::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Self::Latin1),
+            "gbk" | "cp936" => Some(Self::Gbk),
+            "gb18030" => Some(Self::Gb18030),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf-8",
+            Self::Utf8Bom => "utf-8-bom",
+            Self::Utf16Le => "utf-16le",
+            Self::Utf16Be => "utf-16be",
+            Self::Latin1 => "latin1",
+            Self::Gbk => "gbk",
+            Self::Gb18030 => "gb18030",
+        }
+    }
+
+    /** 根据 BOM 和内容猜测文件编码，依次尝试 UTF-8、GBK/GB18030，最后退回 Latin-1 */
+    pub fn detect(bytes: &[u8]) -> Self {
+        Self::detect_text(bytes).unwrap_or(Self::Latin1)
+    }
+
+    /** 与 detect 相同，但内容不是合法的 UTF-8 或 GBK/GB18030 时返回 None 而不退回 Latin-1 */
+    pub fn detect_text(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(UTF8_BOM) {
+            return Some(Self::Utf8Bom);
+        }
+        if bytes.starts_with(UTF16LE_BOM) {
+            return Some(Self::Utf16Le);
+        }
+        if bytes.starts_with(UTF16BE_BOM) {
+            return Some(Self::Utf16Be);
+        }
+        if std::str::from_utf8(bytes).is_ok() {
+            return Some(Self::Utf8);
+        }
+        let text = GB18030.decode_without_bom_handling_and_without_replacement(bytes)?;
+        // GBK 是 GB18030 的子集，能用 GBK 编码回去的就按 GBK 处理
+        let (_, _, unmappable) = GBK.encode(&text);
+        Some(if unmappable { Self::Gb18030 } else { Self::Gbk })
+    }
+
+    /** 按该编码解码，若内容不合法则返回 None */
+    pub fn decode(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            Self::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            Self::Utf8Bom => {
+                String::from_utf8(bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes).to_vec()).ok()
+            }
+            Self::Utf16Le => UTF_16LE
+                .decode_without_bom_handling_and_without_replacement(
+                    bytes.strip_prefix(UTF16LE_BOM).unwrap_or(bytes),
+                )
+                .map(|it| it.into_owned()),
+            Self::Utf16Be => UTF_16BE
+                .decode_without_bom_handling_and_without_replacement(
+                    bytes.strip_prefix(UTF16BE_BOM).unwrap_or(bytes),
+                )
+                .map(|it| it.into_owned()),
+            Self::Latin1 => Some(bytes.iter().map(|&b| b as char).collect()),
+            Self::Gbk | Self::Gb18030 => GB18030
+                .decode_without_bom_handling_and_without_replacement(bytes)
+                .map(|it| it.into_owned()),
+        }
+    }
+
+    pub fn encode(self, text: &str) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Utf8 => Ok(text.as_bytes().to_vec()),
+            Self::Utf8Bom => Ok([UTF8_BOM, text.as_bytes()].concat()),
+            Self::Utf16Le => Ok(UTF16LE_BOM
+                .iter()
+                .copied()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect()),
+            Self::Utf16Be => Ok(UTF16BE_BOM
+                .iter()
+                .copied()
+                .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+                .collect()),
+            Self::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(c).map_err(|_| self.unmappable(c)))
+                .collect(),
+            Self::Gbk | Self::Gb18030 => {
+                let encoder = if self == Self::Gbk { GBK } else { GB18030 };
+                let (bytes, _, unmappable) = encoder.encode(text);
+                if unmappable {
+                    let c = text
+                        .chars()
+                        .find(|c| encoder.encode(c.encode_utf8(&mut [0; 4])).2)
+                        .unwrap_or(char::REPLACEMENT_CHARACTER);
+                    return Err(self.unmappable(c));
+                }
+                Ok(bytes.into_owned())
+            }
+        }
+    }
+
+    pub fn bom(self) -> &'static [u8] {
+        match self {
+            Self::Utf8Bom => UTF8_BOM,
+            Self::Utf16Le => UTF16LE_BOM,
+            Self::Utf16Be => UTF16BE_BOM,
+            _ => &[],
+        }
+    }
+
+    /** 与 encode 相同，但不加 BOM，用于编码文件中间的一段内容 */
+    pub fn encode_without_bom(self, text: &str) -> io::Result<Vec<u8>> {
+        let mut bytes = self.encode(text)?;
+        bytes.drain(..self.bom().len());
+        Ok(bytes)
+    }
+
+    /** ASCII 字符（包括换行符）是否按单字节编码，大文件模式按字节查找换行符，只支持这类编码 */
+    pub fn is_ascii_compatible(self) -> bool {
+        !matches!(self, Self::Utf16Le | Self::Utf16Be)
+    }
+
+    fn unmappable(self, c: char) -> io::Error {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("'{}' cannot be encoded as {}", c, self.name()),
+        )
+    }
+}
@@ -0,0 +1,153 @@
+use std::path::Path;
+
+/** 根据文件扩展名决定的语言相关设置 */
+pub struct FileType {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    /** 在这些字符之后换行时增加一级缩进 */
+    indent_openers: &'static str,
+    /** 自动配对的引号，Rust 的 ' 用于生命周期，不能配对 */
+    quotes: &'static str,
+    /** 行注释前缀，为空表示不支持 */
+    pub line_comment: &'static str,
+    /** 块注释的开始和结束标记 */
+    pub block_comment: Option<(&'static str, &'static str)>,
+}
+
+const PLAIN_TEXT: FileType = FileType {
+    name: "text",
+    extensions: &[],
+    indent_openers: "",
+    quotes: "\"",
+    line_comment: "",
+    block_comment: None,
+};
+
+const FILE_TYPES: &[FileType] = &[
+    FileType {
+        name: "rust",
+        extensions: &["rs"],
+        indent_openers: "{([",
+        quotes: "\"",
+        line_comment: "//",
+        block_comment: Some(("/*", "*/")),
+    },
+    FileType {
+        name: "c",
+        extensions: &[
+            "c", "h", "cc", "cpp", "hpp", "java", "js", "jsx", "ts", "tsx", "go", "cs", "swift",
+            "kt", "css", "json",
+        ],
+        indent_openers: "{([",
+        quotes: "\"'`",
+        line_comment: "//",
+        block_comment: Some(("/*", "*/")),
+    },
+    FileType {
+        name: "python",
+        extensions: &["py"],
+        indent_openers: "{([:",
+        quotes: "\"'",
+        line_comment: "#",
+        block_comment: None,
+    },
+    FileType {
+        name: "shell",
+        extensions: &["sh", "bash", "zsh"],
+        indent_openers: "{(",
+        quotes: "\"'`",
+        line_comment: "#",
+        block_comment: None,
+    },
+    FileType {
+        name: "yaml",
+        extensions: &["yml", "yaml"],
+        indent_openers: "{[:",
+        quotes: "\"'",
+        line_comment: "#",
+        block_comment: None,
+    },
+    FileType {
+        name: "toml",
+        extensions: &["toml"],
+        indent_openers: "{[",
+        quotes: "\"'",
+        line_comment: "#",
+        block_comment: None,
+    },
+    FileType {
+        name: "lua",
+        extensions: &["lua"],
+        indent_openers: "{([",
+        quotes: "\"'",
+        line_comment: "--",
+        block_comment: Some(("--[[", "]]")),
+    },
+    FileType {
+        name: "sql",
+        extensions: &["sql"],
+        indent_openers: "(",
+        quotes: "\"'",
+        line_comment: "--",
+        block_comment: Some(("/*", "*/")),
+    },
+    FileType {
+        name: "haskell",
+        extensions: &["hs"],
+        indent_openers: "{([",
+        quotes: "\"",
+        line_comment: "--",
+        block_comment: Some(("{-", "-}")),
+    },
+];
+
+impl FileType {
+    pub fn from_path(path: Option<&Path>) -> &'static FileType {
+        path.and_then(|it| it.extension())
+            .and_then(|it| it.to_str())
+            .and_then(|extension| {
+                FILE_TYPES
+                    .iter()
+                    .find(|it| it.extensions.contains(&extension))
+            })
+            .unwrap_or(&PLAIN_TEXT)
+    }
+
+    pub fn opens_indent(&self, c: char) -> bool {
+        self.indent_openers.contains(c)
+    }
+
+    /** 该文件类型中会减少缩进的右括号 */
+    pub fn closes_indent(&self, c: char) -> bool {
+        self.indent_openers
+            .chars()
+            .any(|opener| closing_bracket(opener) == Some(c))
+    }
+
+    /** 输入 opener 时自动补全的字符 */
+    pub fn closing_pair(&self, opener: char) -> Option<char> {
+        closing_bracket(opener).or_else(|| self.quotes.contains(opener).then_some(opener))
+    }
+
+    pub fn is_closer(&self, c: char) -> bool {
+        matches!(c, ')' | ']' | '}') || self.quotes.contains(c)
+    }
+}
+
+pub fn opening_bracket(closer: char) -> Option<char> {
+    match closer {
+        ')' => Some('('),
+        ']' => Some('['),
+        '}' => Some('{'),
+        _ => None,
+    }
+}
+
+pub fn closing_bracket(opener: char) -> Option<char> {
+    match opener {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        _ => None,
+    }
+}
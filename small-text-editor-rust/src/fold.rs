@@ -0,0 +1,242 @@
+use crate::bracket;
+use crate::filetype::{self, FileType};
+use crate::TAB_STOP;
+use std::ops::Range;
+
+/** 被折叠（隐藏）的行区间，区间之前的一行是折叠的标题行，仍然显示 */
+#[derive(Default)]
+pub struct Folds {
+    /** 按起始行排序，可以嵌套 */
+    ranges: Vec<Range<usize>>,
+}
+
+impl Folds {
+    pub fn fold(&mut self, range: Range<usize>) {
+        if let Err(at) = self
+            .ranges
+            .binary_search_by_key(&(range.start, range.end), |it| (it.start, it.end))
+        {
+            self.ranges.insert(at, range);
+        }
+    }
+
+    /** 用 f 移动各个折叠，返回 None 的折叠被去掉 */
+    fn map_ranges(&mut self, f: impl Fn(&Range<usize>) -> Option<Range<usize>>) {
+        self.ranges = self
+            .ranges
+            .iter()
+            .filter_map(f)
+            .filter(|it| it.start > 0 && !it.is_empty())
+            .collect();
+        self.ranges.sort_by_key(|it| (it.start, it.end));
+        self.ranges.dedup();
+    }
+
+    /** 在 at 处插入了 count 行，插在标题行之后的行不会被折叠 */
+    pub fn rows_inserted(&mut self, at: usize, count: usize) {
+        self.map_ranges(|range| {
+            let start = if at <= range.start {
+                range.start + count
+            } else {
+                range.start
+            };
+            let end = if at < range.end {
+                range.end + count
+            } else {
+                range.end
+            };
+            Some(start..end)
+        });
+    }
+
+    /** 删除了 rows 中的行，标题行被删除的折叠被去掉 */
+    pub fn rows_removed(&mut self, rows: Range<usize>) {
+        let removed_before = |y: usize| y.clamp(rows.start, rows.end) - rows.start;
+        self.map_ranges(|range| {
+            (!rows.contains(&(range.start - 1))).then(|| {
+                range.start - removed_before(range.start)..range.end - removed_before(range.end)
+            })
+        });
+    }
+
+    /** 第 at 行被连接到上一行 */
+    pub fn row_joined(&mut self, at: usize) {
+        self.rows_removed(at..at + 1);
+    }
+
+    /** rows 中的行被替换成了 count 行 */
+    pub fn rows_replaced(&mut self, rows: Range<usize>, count: usize) {
+        // 逐行替换（例如大小写转换）时折叠保持不变
+        if count == rows.len() {
+            return;
+        }
+        self.rows_removed(rows.clone());
+        self.rows_inserted(rows.start, count);
+    }
+
+    /** rows 中的行被轮换了，与之相交的折叠被展开 */
+    pub fn rows_rotated(&mut self, rows: Range<usize>) {
+        self.map_ranges(|range| {
+            (range.end <= rows.start || range.start > rows.end).then(|| range.clone())
+        });
+    }
+
+    /** 去掉以 header 为标题行的折叠，返回是否存在这样的折叠 */
+    pub fn unfold(&mut self, header: usize) -> bool {
+        let len = self.ranges.len();
+        self.ranges.retain(|it| it.start != header + 1);
+        self.ranges.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    /** 展开所有包含 y 的折叠，使 y 可见 */
+    pub fn reveal(&mut self, y: usize) {
+        self.ranges.retain(|it| !it.contains(&y));
+    }
+
+    /** 包含 y 的最外层折叠 */
+    fn outermost(&self, y: usize) -> Option<&Range<usize>> {
+        self.ranges
+            .iter()
+            .take_while(|it| it.start <= y)
+            .find(|it| it.contains(&y))
+    }
+
+    pub fn is_hidden(&self, y: usize) -> bool {
+        self.outermost(y).is_some()
+    }
+
+    /** 标题行 header 之后隐藏的行数 */
+    pub fn hidden_after(&self, header: usize) -> Option<usize> {
+        if self.is_hidden(header) {
+            return None;
+        }
+        self.outermost(header + 1).map(|it| it.end - header - 1)
+    }
+
+    /** y 之后的第一个可见行 */
+    pub fn next_visible(&self, y: usize) -> usize {
+        let mut next = y + 1;
+        while let Some(range) = self.outermost(next) {
+            next = range.end;
+        }
+        next
+    }
+
+    /** y 之前的第一个可见行 */
+    pub fn previous_visible(&self, y: usize) -> Option<usize> {
+        let previous = y.checked_sub(1)?;
+        Some(self.outermost(previous).map_or(previous, |it| it.start - 1))
+    }
+
+    /** from..to 中可见的行数 */
+    pub fn visible_between(&self, from: usize, to: usize) -> usize {
+        if self.ranges.is_empty() {
+            return to.saturating_sub(from);
+        }
+        let mut count = 0;
+        let mut y = from;
+        while y < to {
+            count += 1;
+            y = self.next_visible(y);
+        }
+        count
+    }
+}
+
+/** 缩进的列数，tab 与显示时一样对齐到 TAB_STOP 的倍数 */
+fn indent_columns(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .fold(0, |column, c| match c {
+            '\t' => column + TAB_STOP - column % TAB_STOP,
+            _ => column + 1,
+        })
+}
+
+/** rows 范围内所有可以折叠的区间（隐藏的行），按起始行排序，每个标题行最多一个。
+以左括号结尾的行折叠到匹配的右括号之前，其他行折叠之后缩进更深的行 */
+pub fn regions<'a>(
+    file_type: &FileType,
+    rows: Range<usize>,
+    get_row: impl Fn(usize) -> &'a str,
+) -> Vec<Range<usize>> {
+    let mut brackets: Vec<Range<usize>> = Vec::new();
+    let mut indented: Vec<Range<usize>> = Vec::new();
+    // 括号：(行, 列, 是否为该行最后一个字符)
+    let mut open: Vec<(usize, usize, bool)> = Vec::new();
+    let mut in_block = false;
+    // 缩进：(行, 缩进列数)，以及上一个非空行
+    let mut indents: Vec<(usize, usize)> = Vec::new();
+    let mut last_non_blank = rows.start;
+    for y in rows.clone() {
+        let line = get_row(y);
+        let mask = bracket::code_mask(file_type, line, &mut in_block);
+        for (x, c) in line.char_indices().filter(|&(x, _)| mask[x]) {
+            if filetype::closing_bracket(c).is_some() {
+                open.push((y, x, false));
+            } else if filetype::opening_bracket(c).is_some() {
+                if let Some((header, _, true)) = open.pop() {
+                    if y > header + 1 {
+                        brackets.push(header + 1..y);
+                    }
+                }
+            }
+        }
+        let last_code = line
+            .char_indices()
+            .rev()
+            .find(|&(x, c)| mask[x] && !c.is_whitespace());
+        if let (Some((x, _)), Some(top)) = (last_code, open.last_mut()) {
+            if top.0 == y && top.1 == x {
+                top.2 = true;
+            }
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let indent = indent_columns(line);
+        while let Some(&(header, header_indent)) = indents.last() {
+            if header_indent < indent {
+                break;
+            }
+            indents.pop();
+            indented.extend(indent_region(header, last_non_blank));
+        }
+        indents.push((y, indent));
+        last_non_blank = y;
+    }
+    while let Some((header, _)) = indents.pop() {
+        indented.extend(indent_region(header, last_non_blank));
+    }
+    // 同一标题行上括号的区间优先，排序是稳定的
+    let mut regions = brackets;
+    regions.append(&mut indented);
+    regions.sort_by_key(|it| it.start);
+    regions.dedup_by_key(|it| it.start);
+    regions
+}
+
+/** 标题行 header 之后到 last 为止的缩进区间 */
+fn indent_region(header: usize, last: usize) -> Option<Range<usize>> {
+    (last > header).then(|| header + 1..last + 1)
+}
+
+/** 每个区间被几个其他区间包含 */
+pub fn depths(regions: &[Range<usize>]) -> Vec<usize> {
+    let mut enclosing: Vec<&Range<usize>> = Vec::new();
+    regions
+        .iter()
+        .map(|region| {
+            while enclosing.last().is_some_and(|it| it.end < region.end) {
+                enclosing.pop();
+            }
+            let depth = enclosing.len();
+            enclosing.push(region);
+            depth
+        })
+        .collect()
+}
@@ -0,0 +1,162 @@
+use std::cmp;
+
+/** 每个匹配的字符的基础分 */
+const MATCH: i64 = 16;
+/** 匹配到路径中一段的开头 */
+const SEGMENT_START: i64 = 30;
+/** 匹配到单词的开头：_-. 或空格之后，或小写到大写的位置 */
+const WORD_START: i64 = 20;
+/** 与上一个匹配的字符相邻 */
+const CONSECUTIVE: i64 = 24;
+/** 匹配到文件名（最后一段）中的字符 */
+const FILE_NAME: i64 = 4;
+/** 两个匹配的字符之间每隔一个字符扣的分 */
+const GAP: i64 = 1;
+
+fn bonus(previous: Option<char>, c: char) -> i64 {
+    match previous {
+        None | Some('/' | '\\') => SEGMENT_START,
+        Some('_' | '-' | '.' | ' ') => WORD_START,
+        Some(p) if p.is_lowercase() && c.is_uppercase() => WORD_START,
+        _ => 0,
+    }
+}
+
+/**
+ * pattern 的字符（忽略空白）按顺序出现在 candidate 中时返回得分，越高越好，否则返回 None。
+ * pattern 中有大写字母时区分大小写。用动态规划在所有匹配方式中找得分最高的一种
+ */
+pub fn score(pattern: &str, candidate: &str) -> Option<i64> {
+    let case_sensitive = pattern.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+    let pattern: Vec<char> = pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(fold)
+        .collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let file_name_start = chars.iter().rposition(|&c| c == '/').map_or(0, |i| i + 1);
+    let bonuses: Vec<i64> = (0..chars.len())
+        .map(|j| {
+            let previous = j.checked_sub(1).map(|k| chars[k]);
+            bonus(previous, chars[j]) + if j >= file_name_start { FILE_NAME } else { 0 }
+        })
+        .collect();
+    let folded: Vec<char> = chars.iter().map(|&c| fold(c)).collect();
+    // previous[j]：pattern 的上一个字符匹配在 j 处时前面各字符的最高得分
+    let mut previous: Vec<Option<i64>> = vec![Some(0); chars.len()];
+    for (i, &p) in pattern.iter().enumerate() {
+        let mut current = vec![None; chars.len()];
+        // 所有 k < j 中 previous[k] + GAP * k 的最大值，减去 GAP * (j - 1) 即为隔开匹配的得分
+        let mut best_before: Option<i64> = None;
+        for j in 0..chars.len() {
+            if folded[j] == p {
+                current[j] = if i == 0 {
+                    Some(MATCH + bonuses[j])
+                } else {
+                    let gapped = best_before.map(|best| best - GAP * (j as i64 - 1));
+                    let consecutive = j
+                        .checked_sub(1)
+                        .and_then(|k| previous[k])
+                        .map(|score| score + CONSECUTIVE);
+                    cmp::max(gapped, consecutive).map(|score| score + MATCH + bonuses[j])
+                };
+            }
+            if let Some(score) = previous[j].filter(|_| i > 0) {
+                best_before = cmp::max(best_before, Some(score + GAP * j as i64));
+            }
+        }
+        previous = current;
+    }
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    previous.into_iter().flatten().max()
+}
+
+/** 按得分从高到低排列匹配的候选，得分相同时短的在前，最多返回 limit 个 */
+pub fn rank<'a>(pattern: &str, candidates: &'a [String], limit: usize) -> Vec<&'a str> {
+    let mut matches: Vec<(i64, &str)> = candidates
+        .iter()
+        .filter_map(|candidate| Some((score(pattern, candidate)?, candidate.as_str())))
+        .collect();
+    matches.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then(a.1.len().cmp(&b.1.len()))
+            .then(a.1.cmp(b.1))
+    });
+    matches.into_iter().take(limit).map(|(_, it)| it).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_pattern_matches_everything() {
+        assert_eq!(score("", ""), Some(0));
+        assert_eq!(score("", "src/main.rs"), Some(0));
+        assert_eq!(score("  ", "src/main.rs"), Some(0));
+    }
+
+    #[test]
+    fn characters_must_appear_in_order() {
+        assert!(score("mr", "src/main.rs").is_some());
+        assert!(score("rm", "main.rs").is_none());
+        assert!(score("mainx", "src/main.rs").is_none());
+        assert!(score("a", "").is_none());
+    }
+
+    #[test]
+    fn whitespace_in_pattern_is_ignored() {
+        assert_eq!(score("ma in", "src/main.rs"), score("main", "src/main.rs"));
+    }
+
+    #[test]
+    fn uppercase_pattern_is_case_sensitive() {
+        assert!(score("readme", "README.md").is_some());
+        assert!(score("README", "readme.md").is_none());
+        assert!(score("Main", "src/Main.java").is_some());
+    }
+
+    #[test]
+    fn consecutive_matches_score_higher() {
+        assert!(score("abc", "abcxyz").unwrap() > score("abc", "axbxcx").unwrap());
+    }
+
+    #[test]
+    fn word_and_segment_starts_score_higher() {
+        assert!(score("b", "a/b").unwrap() > score("b", "ab").unwrap());
+        assert!(score("b", "a_b").unwrap() > score("b", "ab").unwrap());
+        assert!(score("b", "aB").unwrap() > score("b", "ab").unwrap());
+    }
+
+    #[test]
+    fn best_alignment_is_chosen() {
+        // 开头的 m 与 a 隔得很远，最好的匹配是文件名中相邻的 ma
+        assert_eq!(
+            score("ma", "m/x/main"),
+            Some(2 * MATCH + SEGMENT_START + 2 * FILE_NAME + CONSECUTIVE)
+        );
+    }
+
+    #[test]
+    fn rank_orders_by_score_then_length() {
+        let candidates: Vec<String> = ["src/lib/main.rs", "main.rs", "xmxaxixn", "other.rs"]
+            .iter()
+            .map(|it| it.to_string())
+            .collect();
+        assert_eq!(
+            rank("main", &candidates, 10),
+            vec!["main.rs", "src/lib/main.rs", "xmxaxixn"]
+        );
+        assert_eq!(rank("main", &candidates, 1), vec!["main.rs"]);
+        assert_eq!(rank("", &candidates, 10).len(), 4);
+    }
+}
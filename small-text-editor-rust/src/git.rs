@@ -0,0 +1,113 @@
+use std::cmp;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::diff::{self, Hunk};
+use crate::encoding::Encoding;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    /** 该行前面（文件末尾时为该行后面）有被删除的行 */
+    Deleted,
+}
+
+/** 与文件在 HEAD 中的版本比较，标出修改过的行 */
+pub struct GitGutter {
+    /** HEAD 中的各行 */
+    base: Vec<String>,
+    hunks: Vec<Hunk>,
+    /** 上次比较时的修改计数，不同时需要重新比较 */
+    version: Option<u64>,
+}
+
+impl GitGutter {
+    /** 通过 git 命令读取文件在 HEAD 中的内容，不在仓库中、未被跟踪或没有 git 时返回 None */
+    pub fn load(file: &Path) -> Option<Self> {
+        let file = fs::canonicalize(file).ok()?;
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(file.parent()?)
+            .arg("show")
+            .arg(format!("HEAD:./{}", file.file_name()?.to_string_lossy()))
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let contents = Encoding::detect(&output.stdout).decode(&output.stdout)?;
+        Some(Self {
+            base: contents.lines().map(String::from).collect(),
+            hunks: Vec::new(),
+            version: None,
+        })
+    }
+
+    /** 内容在比较之后被修改过（version 不同） */
+    pub fn is_outdated(&self, version: u64) -> bool {
+        self.version != Some(version)
+    }
+
+    /** 内容在比较之后被修改过时重新比较 */
+    pub fn update<'a>(&mut self, rows: impl Iterator<Item = &'a str>, version: u64) {
+        if !self.is_outdated(version) {
+            return;
+        }
+        let rows: Vec<&str> = rows.collect();
+        let base: Vec<&str> = self.base.iter().map(String::as_str).collect();
+        self.hunks = diff::diff(&base, &rows);
+        self.version = Some(version);
+    }
+
+    /** 下次 update 时一定重新比较 */
+    pub fn invalidate(&mut self) {
+        self.version = None;
+    }
+
+    /** 纯删除的修改标在删除位置的下一行，删除到文件末尾时标在最后一行 */
+    fn marker_row(hunk: &Hunk, number_of_rows: usize) -> usize {
+        cmp::min(hunk.new.start, number_of_rows.saturating_sub(1))
+    }
+
+    pub fn change(&self, row: usize, number_of_rows: usize) -> Option<Change> {
+        self.hunk_at(row, number_of_rows).map(|hunk| {
+            if hunk.new.is_empty() {
+                Change::Deleted
+            } else if hunk.old.is_empty() {
+                Change::Added
+            } else {
+                Change::Modified
+            }
+        })
+    }
+
+    /** row 所在的修改 */
+    pub fn hunk_at(&self, row: usize, number_of_rows: usize) -> Option<&Hunk> {
+        self.hunks.iter().find(|hunk| {
+            hunk.new.contains(&row)
+                || (hunk.new.is_empty() && Self::marker_row(hunk, number_of_rows) == row)
+        })
+    }
+
+    /** row 之后（forward）或之前的第一处修改的起始行 */
+    pub fn next_hunk(&self, row: usize, number_of_rows: usize, forward: bool) -> Option<usize> {
+        let starts = self
+            .hunks
+            .iter()
+            .map(|hunk| Self::marker_row(hunk, number_of_rows));
+        if forward {
+            starts.filter(|&start| start > row).min()
+        } else {
+            starts.filter(|&start| start < row).max()
+        }
+    }
+
+    /** hunk 在 HEAD 中的内容 */
+    pub fn base_lines(&self, hunk: &Hunk) -> &[String] {
+        &self.base[hunk.old.clone()]
+    }
+}
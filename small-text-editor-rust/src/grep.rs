@@ -0,0 +1,121 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use crate::encoding::Encoding;
+use crate::hex::HexView;
+use crate::large_file::LARGE_FILE_THRESHOLD;
+use crate::project;
+
+/** 最多收集的结果数，超过后停止搜索 */
+const MAX_HITS: usize = 10_000;
+
+/** 一处匹配，line 从 0 开始，column 为行内的字节位置 */
+pub struct Hit {
+    /** 相对于项目根目录的路径 */
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path, self.line + 1, self.text.trim())
+    }
+}
+
+/** 在后台线程中搜索项目里的所有文本文件 */
+pub struct ProjectSearch {
+    pub root: PathBuf,
+    pub pattern: String,
+    pub hits: Vec<Hit>,
+    /** 上次在结果列表中选中的一项 */
+    pub selected: usize,
+    /** 搜索结束后为 None */
+    receiver: Option<Receiver<Hit>>,
+}
+
+impl ProjectSearch {
+    pub fn start(root: PathBuf, pattern: String, whole_word: bool) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let (thread_root, thread_pattern) = (root.clone(), pattern.clone());
+        thread::spawn(move || search(&thread_root, &thread_pattern, whole_word, sender));
+        Self {
+            root,
+            pattern,
+            hits: Vec::new(),
+            selected: 0,
+            receiver: Some(receiver),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    /** 取出后台线程到目前为止找到的结果，返回是否有变化 */
+    pub fn receive(&mut self) -> bool {
+        let receiver = match &self.receiver {
+            Some(receiver) => receiver,
+            None => return false,
+        };
+        let mut finished = false;
+        let before = self.hits.len();
+        loop {
+            match receiver.try_recv() {
+                Ok(hit) => self.hits.push(hit),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    finished = true;
+                    break;
+                }
+            }
+        }
+        if finished {
+            self.receiver = None;
+        }
+        finished || self.hits.len() > before
+    }
+}
+
+/** 读取并解码文本文件，二进制文件、超过大文件阈值的文件和无法读取的文件返回 None */
+pub fn read_text(path: &Path) -> Option<(Encoding, String)> {
+    if fs::metadata(path).ok()?.len() >= LARGE_FILE_THRESHOLD {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    if HexView::is_binary(&bytes) {
+        return None;
+    }
+    let encoding = Encoding::detect(&bytes);
+    Some((encoding, encoding.decode(&bytes)?))
+}
+
+/** 逐个文件查找 pattern，每行只报告第一处匹配，接收端被丢弃（开始了新的搜索）时提前结束 */
+fn search(root: &Path, pattern: &str, whole_word: bool, sender: Sender<Hit>) {
+    let mut hits = 0;
+    for path in project::walk(root) {
+        let contents = match read_text(&path) {
+            Some((_, contents)) => contents,
+            None => continue,
+        };
+        let relative = project::relative(root, &path);
+        for (line, text) in contents.lines().enumerate() {
+            if let Some(column) = crate::find_keyword(text, pattern, whole_word) {
+                let hit = Hit {
+                    path: relative.clone(),
+                    line,
+                    column,
+                    text: text.into(),
+                };
+                hits += 1;
+                if sender.send(hit).is_err() || hits >= MAX_HITS {
+                    return;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crossterm::event::KeyCode;
+use std::cmp;
+
+pub const BYTES_PER_ROW: usize = 16;
+/** 偏移量列 "00000000" 加两个空格 */
+const OFFSET_WIDTH: usize = 10;
+/** 用来判断是否为二进制文件的采样长度 */
+const SAMPLE_LEN: usize = 8192;
+
+pub struct HexView {
+    pub bytes: Vec<u8>,
+    /** 光标所在字节的偏移 */
+    pub cursor: usize,
+    /** 光标是否在该字节的低 4 位上 */
+    pub low_nibble: bool,
+    pub row_offset: usize,
+    /** 从文本模式切换过来时原来的各行，切换回去时用来移动书签和折叠 */
+    pub text_rows: Option<Vec<String>>,
+}
+
+impl HexView {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            cursor: 0,
+            low_nibble: false,
+            row_offset: 0,
+            text_rows: None,
+        }
+    }
+
+    /** 含有 NUL 或大量控制字符的内容视为二进制（带 BOM 的 UTF-16 除外） */
+    pub fn is_binary(bytes: &[u8]) -> bool {
+        if bytes.starts_with(&[0xFF, 0xFE]) || bytes.starts_with(&[0xFE, 0xFF]) {
+            return false;
+        }
+        let sample = &bytes[..cmp::min(bytes.len(), SAMPLE_LEN)];
+        if sample.contains(&0) {
+            return true;
+        }
+        let control = sample
+            .iter()
+            .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B))
+            .count();
+        control * 10 > sample.len()
+    }
+
+    /** 解析 "de ad be ef" 或 "deadbeef" 形式的字节序列 */
+    pub fn parse_pattern(pattern: &str) -> Option<Vec<u8>> {
+        let digits: String = pattern.split_whitespace().collect();
+        if digits.is_empty() || !digits.len().is_multiple_of(2) {
+            return None;
+        }
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+
+    pub fn number_of_rows(&self) -> usize {
+        self.bytes.len().div_ceil(BYTES_PER_ROW)
+    }
+
+    pub fn render_row(&self, row: usize) -> String {
+        let start = row * BYTES_PER_ROW;
+        let chunk = &self.bytes[start..cmp::min(start + BYTES_PER_ROW, self.bytes.len())];
+        let mut render = format!("{:08x}  ", start);
+        for i in 0..BYTES_PER_ROW {
+            if i == BYTES_PER_ROW / 2 {
+                render.push(' ');
+            }
+            match chunk.get(i) {
+                Some(byte) => render.push_str(&format!("{:02x} ", byte)),
+                None => render.push_str("   "),
+            }
+        }
+        render.push_str(" |");
+        render.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        render.push('|');
+        render
+    }
+
+    /** 光标在屏幕上的列 */
+    pub fn cursor_column(&self) -> usize {
+        let i = self.cursor % BYTES_PER_ROW;
+        OFFSET_WIDTH + i * 3 + usize::from(i >= BYTES_PER_ROW / 2) + usize::from(self.low_nibble)
+    }
+
+    pub fn scroll(&mut self, screen_rows: usize) {
+        let cursor_row = self.cursor / BYTES_PER_ROW;
+        self.row_offset = cmp::min(self.row_offset, cursor_row);
+        if cursor_row >= self.row_offset + screen_rows {
+            self.row_offset = cursor_row + 1 - screen_rows;
+        }
+    }
+
+    pub fn move_cursor(&mut self, direction: KeyCode, screen_rows: usize) {
+        let last = self.bytes.len().saturating_sub(1);
+        match direction {
+            KeyCode::Left => {
+                if self.low_nibble {
+                    self.low_nibble = false;
+                } else if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.low_nibble = true;
+                }
+            }
+            KeyCode::Right => {
+                if !self.low_nibble {
+                    self.low_nibble = true;
+                } else if self.cursor < last {
+                    self.cursor += 1;
+                    self.low_nibble = false;
+                }
+            }
+            KeyCode::Up => self.cursor = self.cursor.saturating_sub(BYTES_PER_ROW),
+            KeyCode::Down if self.cursor + BYTES_PER_ROW <= last => self.cursor += BYTES_PER_ROW,
+            KeyCode::Home => {
+                self.cursor -= self.cursor % BYTES_PER_ROW;
+                self.low_nibble = false;
+            }
+            KeyCode::End => {
+                self.cursor = cmp::min(
+                    self.cursor - self.cursor % BYTES_PER_ROW + BYTES_PER_ROW - 1,
+                    last,
+                );
+                self.low_nibble = true;
+            }
+            KeyCode::PageUp => {
+                self.cursor = self.cursor.saturating_sub(screen_rows * BYTES_PER_ROW)
+            }
+            KeyCode::PageDown => {
+                self.cursor = cmp::min(self.cursor + screen_rows * BYTES_PER_ROW, last)
+            }
+            _ => {}
+        }
+    }
+
+    /** 覆盖光标处的半个字节，并把光标移到下一个半字节 */
+    pub fn overwrite_nibble(&mut self, digit: u8) -> bool {
+        let byte = match self.bytes.get_mut(self.cursor) {
+            Some(byte) => byte,
+            None => return false,
+        };
+        *byte = if self.low_nibble {
+            (*byte & 0xF0) | digit
+        } else {
+            (*byte & 0x0F) | (digit << 4)
+        };
+        self.move_cursor(KeyCode::Right, 0);
+        true
+    }
+
+    pub fn goto(&mut self, offset: usize) -> bool {
+        if offset >= self.bytes.len() {
+            return false;
+        }
+        self.cursor = offset;
+        self.low_nibble = false;
+        true
+    }
+
+    /** 从光标之后开始查找，找不到时从头开始 */
+    pub fn find(&self, pattern: &[u8]) -> Option<usize> {
+        let find_from = |start: usize| {
+            self.bytes
+                .get(start..)?
+                .windows(pattern.len())
+                .position(|window| window == pattern)
+                .map(|index| index + start)
+        };
+        find_from(self.cursor + 1).or_else(|| find_from(0))
+    }
+}
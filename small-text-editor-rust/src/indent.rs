@@ -0,0 +1,81 @@
+/** 推断缩进方式时最多检查的行数 */
+const DETECT_LINES: usize = 1000;
+
+/** 行首的空白部分 */
+pub fn leading_whitespace(s: &str) -> &str {
+    &s[..s.len() - s.trim_start().len()]
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct IndentStyle {
+    pub use_tabs: bool,
+    /** 每一级缩进的列数 */
+    pub width: usize,
+}
+
+impl IndentStyle {
+    /** 根据已有内容推断缩进方式，内容中没有缩进时返回 None */
+    pub fn detect<'a>(lines: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let (mut tab_lines, mut space_lines) = (0, 0);
+        // 相邻两行缩进增加的空格数出现的次数
+        let mut deltas = [0usize; 9];
+        let mut previous = 0;
+        for line in lines.take(DETECT_LINES) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let indent = leading_whitespace(line);
+            if indent.starts_with('\t') {
+                tab_lines += 1;
+                continue;
+            }
+            let spaces = indent.len() - indent.trim_start_matches(' ').len();
+            if spaces > 0 {
+                space_lines += 1;
+            }
+            if spaces > previous && spaces - previous < deltas.len() {
+                deltas[spaces - previous] += 1;
+            }
+            previous = spaces;
+        }
+        if tab_lines == 0 && space_lines == 0 {
+            return None;
+        }
+        // 缩进一个空格多半是注释对齐，不计入
+        let width = (2..deltas.len())
+            .filter(|&width| deltas[width] > 0)
+            .max_by_key(|&width| (deltas[width], usize::MAX - width))
+            .unwrap_or(4);
+        Some(Self {
+            use_tabs: tab_lines > space_lines,
+            width,
+        })
+    }
+
+    /** 一级缩进 */
+    pub fn unit(&self) -> String {
+        if self.use_tabs {
+            "\t".into()
+        } else {
+            " ".repeat(self.width)
+        }
+    }
+
+    /** 按当前风格重写行首缩进，tab 视为一级缩进 */
+    pub fn reindent(&self, line: &str) -> String {
+        let indent = leading_whitespace(line);
+        let columns = indent.chars().fold(0, |column, c| {
+            if c == '\t' {
+                column + self.width - column % self.width
+            } else {
+                column + 1
+            }
+        });
+        let new_indent = if self.use_tabs {
+            "\t".repeat(columns / self.width) + &" ".repeat(columns % self.width)
+        } else {
+            " ".repeat(columns)
+        };
+        new_indent + &line[indent.len()..]
+    }
+}
@@ -0,0 +1,341 @@
+use memchr::{memchr, memchr_iter, memmem};
+use memmap2::Mmap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::encoding::Encoding;
+
+/** 超过该大小的文件以大文件模式打开 */
+pub const LARGE_FILE_THRESHOLD: u64 = 64 * 1024 * 1024;
+/** 每隔多少行记录一次行首偏移 */
+const CHECKPOINT: usize = 1024;
+/** 每次扩展索引时最多扫描的行数 */
+const INDEX_STEP: usize = 64 * 1024;
+
+/** 被编辑过的区间：原文件中 original 行被替换为 rows */
+struct Piece {
+    original: Range<usize>,
+    rows: Vec<String>,
+}
+
+pub struct LargeFile {
+    map: Mmap,
+    /** checkpoints[k] 是第 k * CHECKPOINT 行的起始字节 */
+    checkpoints: Vec<usize>,
+    /** 已知起始位置的行数 */
+    known_lines: usize,
+    /** 下一次扫描换行符的起点 */
+    scanned: usize,
+    complete: bool,
+    pieces: Vec<Piece>,
+    /** 当前载入到 EditorRows 中的窗口的首行（显示行号） */
+    pub window_start: usize,
+    /** 窗口对应的原文件行区间 */
+    window_original: Range<usize>,
+    /** 窗口内容是否与原文件不同，换出时需要保存为 Piece */
+    pub window_dirty: bool,
+    /** 窗口中第一个无法按 encoding 解码的行（显示行号），这样的窗口只能查看不能修改 */
+    pub window_undecodable: Option<usize>,
+    /** 读取各行时使用的编码 */
+    encoding: Encoding,
+    /** 修改过的行之间的换行符，与文件第一行的换行符相同 */
+    line_ending: &'static str,
+}
+
+fn unsupported(encoding: Encoding) -> io::Error {
+    io::Error::other(format!(
+        "{} is not supported for large files",
+        encoding.name()
+    ))
+}
+
+impl LargeFile {
+    pub fn open(path: &Path, encoding: Encoding) -> io::Result<Self> {
+        if !encoding.is_ascii_compatible() {
+            return Err(unsupported(encoding));
+        }
+        let file = File::open(path)?;
+        // 映射期间文件被其他程序截断会导致 SIGBUS，这里和其他编辑器一样接受这个风险
+        let map = unsafe { Mmap::map(&file)? };
+        let len = map.len();
+        let crlf = memchr(b'\n', &map).is_some_and(|index| index > 0 && map[index - 1] == b'\r');
+        Ok(Self {
+            map,
+            checkpoints: vec![0],
+            known_lines: usize::from(len > 0),
+            scanned: 0,
+            complete: len == 0,
+            pieces: Vec::new(),
+            window_start: 0,
+            window_original: 0..0,
+            window_dirty: false,
+            window_undecodable: None,
+            encoding,
+            line_ending: if crlf { "\r\n" } else { "\n" },
+        })
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /** 编辑后的总行数，window_len 为当前窗口内的行数 */
+    pub fn number_of_rows(&self, window_len: usize) -> usize {
+        let added: usize = self.pieces.iter().map(|it| it.rows.len()).sum::<usize>() + window_len;
+        let removed: usize = self
+            .pieces
+            .iter()
+            .map(|it| it.original.len())
+            .sum::<usize>()
+            + self.window_original.len();
+        self.known_lines + added - removed
+    }
+
+    /** 扩展索引直到总行数不少于 rows 或扫描到文件末尾 */
+    pub fn index_to(&mut self, rows: usize, window_len: usize) {
+        while !self.complete && self.number_of_rows(window_len) < rows {
+            self.index_more(INDEX_STEP);
+        }
+    }
+
+    fn index_more(&mut self, lines: usize) {
+        for _ in 0..lines {
+            match memchr(b'\n', &self.map[self.scanned..]) {
+                Some(index) if self.scanned + index + 1 < self.map.len() => {
+                    self.scanned += index + 1;
+                    if self.known_lines.is_multiple_of(CHECKPOINT) {
+                        self.checkpoints.push(self.scanned);
+                    }
+                    self.known_lines += 1;
+                }
+                _ => {
+                    self.complete = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn line_start(&self, line: usize) -> usize {
+        let mut start = self.checkpoints[line / CHECKPOINT];
+        for _ in 0..line % CHECKPOINT {
+            start += memchr(b'\n', &self.map[start..]).unwrap() + 1;
+        }
+        start
+    }
+
+    /** 行尾位置（不含换行符） */
+    fn line_end(&self, start: usize) -> usize {
+        memchr(b'\n', &self.map[start..]).map_or(self.map.len(), |index| start + index)
+    }
+
+    /** 读取各行，无法解码的行按 UTF-8 有损解码，同时返回第一个这样的行在结果中的位置 */
+    fn read_lines(&self, lines: Range<usize>) -> (Vec<String>, Option<usize>) {
+        let mut start = self.line_start(lines.start);
+        let mut undecodable = None;
+        let rows = lines
+            .enumerate()
+            .map(|(i, _)| {
+                let end = self.line_end(start);
+                let line = &self.map[start..end];
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                start = end + 1;
+                self.encoding.decode(line).unwrap_or_else(|| {
+                    undecodable.get_or_insert(i);
+                    String::from_utf8_lossy(line).into_owned()
+                })
+            })
+            .collect();
+        (rows, undecodable)
+    }
+
+    /** 把窗口内容换出，有修改时记录为 Piece */
+    pub fn flush_window(&mut self, rows: Vec<String>) {
+        let original = std::mem::replace(&mut self.window_original, 0..0);
+        self.window_start = 0;
+        self.window_undecodable = None;
+        if !std::mem::take(&mut self.window_dirty) {
+            return;
+        }
+        let at = self.pieces.partition_point(|it| {
+            (it.original.start, it.original.end) <= (original.start, original.end)
+        });
+        self.pieces.insert(at, Piece { original, rows });
+    }
+
+    /** 载入显示行 wanted 所在的窗口，与之相交的 Piece 会被整体并入窗口 */
+    pub fn load_window(&mut self, wanted: Range<usize>) -> Vec<String> {
+        let mut rows = Vec::new();
+        let mut start: Option<(usize, usize)> = None;
+        let mut original_end = 0;
+        let mut absorbed = self.pieces.len()..self.pieces.len();
+        let mut undecodable = None;
+        let (mut display, mut original) = (0, 0);
+        for j in 0..=self.pieces.len() {
+            let gap_end = self
+                .pieces
+                .get(j)
+                .map_or(self.known_lines, |it| it.original.start);
+            let gap_len = gap_end - original;
+            let from = wanted.start.max(display);
+            let to = wanted.end.min(display + gap_len);
+            if from < to {
+                let line = original + from - display;
+                start.get_or_insert((from, line));
+                let (lines, first) = self.read_lines(line..line + to - from);
+                rows.extend(lines);
+                undecodable = undecodable.or(first.map(|i| from + i));
+                original_end = line + to - from;
+            }
+            display += gap_len;
+            original = gap_end;
+            let piece = match self.pieces.get(j) {
+                Some(piece) if display < wanted.end => piece,
+                _ => break,
+            };
+            if start.is_some() || display + piece.rows.len() > wanted.start {
+                start.get_or_insert((display, piece.original.start));
+                rows.extend(piece.rows.iter().cloned());
+                original_end = piece.original.end;
+                absorbed.start = absorbed.start.min(j);
+                absorbed.end = j + 1;
+            }
+            display += piece.rows.len();
+            original = piece.original.end;
+        }
+        let (window_start, original_start) = start.unwrap_or((display, original));
+        self.window_start = window_start;
+        self.window_original = original_start..original_end.max(original_start);
+        self.window_dirty = !absorbed.is_empty();
+        self.window_undecodable = undecodable;
+        self.pieces.drain(absorbed);
+        rows
+    }
+
+    /** 按显示顺序遍历各段：(显示起始行, 原文件行区间或修改后的行) */
+    fn for_each_segment(&self, mut f: impl FnMut(usize, Result<Range<usize>, &[String]>) -> bool) {
+        let (mut display, mut original) = (0, 0);
+        for piece in self.pieces.iter().map(Some).chain(Some(None)) {
+            let gap_end = piece.map_or(self.known_lines, |it| it.original.start);
+            if gap_end > original && !f(display, Ok(original..gap_end)) {
+                return;
+            }
+            display += gap_end - original;
+            if let Some(piece) = piece {
+                if !piece.rows.is_empty() && !f(display, Err(&piece.rows)) {
+                    return;
+                }
+                display += piece.rows.len();
+                original = piece.original.end;
+            }
+        }
+    }
+
+    /** 查找第一个包含 keyword 的行，调用前需要先换出窗口 */
+    pub fn find(&mut self, keyword: &str, whole_word: bool) -> Option<usize> {
+        self.index_to(usize::MAX, 0);
+        // 未修改的部分按原文件的编码查找，keyword 无法编码时这些部分中不可能有匹配
+        let pattern = self.encoding.encode_without_bom(keyword).ok();
+        let mut found = None;
+        self.for_each_segment(|display, segment| {
+            found = match segment {
+                Ok(_) if pattern.is_none() => None,
+                Ok(lines) => {
+                    let pattern = pattern.as_deref().unwrap_or_default();
+                    let start = self.line_start(lines.start);
+                    let end = self.line_end(self.line_start(lines.end - 1));
+                    let bytes = &self.map[start..end];
+                    memmem::find_iter(bytes, pattern)
+                        .find(|&index| {
+                            !whole_word || is_word_boundary(bytes, index, index + pattern.len())
+                        })
+                        .map(|index| display + memchr_iter(b'\n', &bytes[..index]).count())
+                }
+                Err(rows) => rows
+                    .iter()
+                    .position(|row| crate::find_keyword(row, keyword, whole_word).is_some())
+                    .map(|index| display + index),
+            };
+            found.is_none()
+        });
+        found
+    }
+
+    /**
+     * 先写入临时文件再替换原文件。未修改的部分直接从映射中拷贝，
+     * 修改过的行按 encoding 编码，行之间使用原文件的换行符
+     */
+    pub fn save(&mut self, path: &Path, encoding: Encoding) -> io::Result<usize> {
+        if !encoding.is_ascii_compatible() {
+            return Err(unsupported(encoding));
+        }
+        self.index_to(usize::MAX, 0);
+        let mut tmp_name = path.as_os_str().to_owned();
+        tmp_name.push(".pound-save");
+        let mut writer = BufWriter::new(File::create(&tmp_name)?);
+        // BOM 只写在开头，原文件开头的 BOM 不再拷贝
+        let bom = encoding.bom();
+        writer.write_all(bom)?;
+        let mut written = bom.len();
+        let original_bom = Encoding::Utf8Bom.bom();
+        // 拷贝的部分包含行尾的 \r，之后只需要 \n；修改过的行之后需要完整的换行符
+        let line_ending = self.line_ending;
+        let ending_after = |edited: bool| -> &[u8] {
+            if edited {
+                line_ending.as_bytes()
+            } else {
+                b"\n"
+            }
+        };
+        let mut previous: Option<bool> = None;
+        let mut result = Ok(());
+        self.for_each_segment(|_, segment| {
+            let separator = previous.map_or(&b""[..], ending_after);
+            previous = Some(segment.is_err());
+            result = writer.write_all(separator).and_then(|_| match segment {
+                Ok(lines) => {
+                    let mut start = self.line_start(lines.start);
+                    if start == 0 && self.map.starts_with(original_bom) {
+                        start = original_bom.len();
+                    }
+                    let end = self.line_end(self.line_start(lines.end - 1));
+                    written += end - start;
+                    writer.write_all(&self.map[start..end])
+                }
+                Err(rows) => {
+                    let contents = encoding.encode_without_bom(&rows.join(line_ending))?;
+                    written += contents.len();
+                    writer.write_all(&contents)
+                }
+            });
+            written += separator.len();
+            result.is_ok()
+        });
+        if let Err(err) = result {
+            drop(writer);
+            let _ = fs::remove_file(&tmp_name);
+            return Err(err);
+        }
+        if self.map.last() == Some(&b'\n') {
+            let ending = ending_after(previous == Some(true));
+            writer.write_all(ending)?;
+            written += ending.len();
+        }
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp_name, path)?;
+        Ok(written)
+    }
+}
+
+/** bytes[start..end] 两侧是否都不是单词字符 */
+fn is_word_boundary(bytes: &[u8], start: usize, end: usize) -> bool {
+    let before = String::from_utf8_lossy(&bytes[start.saturating_sub(4)..start]);
+    let after = String::from_utf8_lossy(&bytes[end..(end + 4).min(bytes.len())]);
+    !before.chars().next_back().is_some_and(crate::is_word_char)
+        && !after.chars().next().is_some_and(crate::is_word_char)
+}
@@ -1,21 +1,72 @@
+mod bracket;
+mod browser;
+mod config;
+mod diff;
+mod encoding;
+mod filetype;
+mod fold;
+mod fuzzy;
+mod git;
+mod grep;
+mod hex;
+mod indent;
+mod large_file;
+mod marks;
+mod popup;
+mod project;
+mod replace;
+mod transform;
+mod undo;
+
 use crossterm::event::*;
+use crossterm::style::Stylize;
 use crossterm::terminal::ClearType;
 use crossterm::{cursor, event, execute, queue, style, terminal};
 use std::cmp::Ordering;
 use std::io::{stdout, ErrorKind, Write};
+use std::ops::Range;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use std::{cmp, env, fs, io};
 
+use config::Config;
+use diff::DiffRow;
+use encoding::Encoding;
+use filetype::FileType;
+use fold::Folds;
+use git::{Change, GitGutter};
+use grep::ProjectSearch;
+use hex::HexView;
+use indent::{leading_whitespace, IndentStyle};
+use large_file::LargeFile;
+use marks::Marks;
+use popup::Popup;
+use replace::FileChanges;
+use transform::{Case, SortOptions};
+use undo::{Edit, History};
+
 const VERSION: &str = "0.0.1";
 const TAB_STOP: usize = 8;
 const QUIT_TIMES: u8 = 3;
+/** 两次点击间隔小于该值时视为双击 */
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+/** 模糊查找文件时最多列出的候选数 */
+const FUZZY_RESULTS: usize = 1000;
+/** 显示项目搜索结果时检查新结果的间隔 */
+const SEARCH_POLL: Duration = Duration::from_millis(100);
+/** 停止输入超过该时间后才重新比较 git 修改标记，避免每次按键都比较整个文件 */
+const GIT_DELAY: Duration = Duration::from_millis(300);
+/** 统一格式的差异中每处修改前后显示的不变行数 */
+const DIFF_CONTEXT: usize = 3;
+/** 滚轮每次滚动的行数 */
+const WHEEL_STEP: usize = 3;
 
 struct CleanUp;
 
 impl Drop for CleanUp {
     fn drop(&mut self) {
         terminal::disable_raw_mode().expect("Unable to disable raw mode");
+        execute!(stdout(), DisableMouseCapture, DisableBracketedPaste).expect("error");
         Output::clear_screen().expect("error");
     }
 }
@@ -23,22 +74,65 @@ impl Drop for CleanUp {
 #[macro_export]
 macro_rules! prompt {
     ($output:expr,$args:tt) => {
-        prompt!($output, $args, callback = |_, _| {})
+        prompt!($output, $args, callback = |_, _, _| {})
+    };
+    ($output:expr,$args:tt, complete = $complete:expr) => {
+        prompt!(
+            $output,
+            $args,
+            callback = |_, _, _| {},
+            complete = $complete
+        )
+    };
+    ($output:expr,$args:tt, callback = $callback:expr) => {
+        prompt!(
+            $output,
+            $args,
+            callback = $callback,
+            complete = |_: &str| None
+        )
+    };
+    ($output:expr,$args:tt, callback = $callback:expr, complete = $complete:expr) => {
+        prompt!(
+            $output,
+            $args,
+            callback = $callback,
+            complete = $complete,
+            allow_empty = false
+        )
     };
-    ($output:expr,$args:tt, callback = $callback:expr) => {{
+    // callback 在输入改变或按下其他键时调用，参数为 output、当前输入和按键
+    // complete 在按下 Tab 时返回补全后的输入，返回 None 时 Tab 作为普通字符输入
+    // allow_empty 为 true 时输入为空也可以按 Enter 确认，返回 Some("")
+    ($output:expr,$args:tt, callback = $callback:expr, complete = $complete:expr, allow_empty = $allow_empty:expr) => {{
         let output: &mut Output = $output;
         let mut input = String::with_capacity(32);
+        let mut accepted = false;
         loop {
             output.status_message.set_message(format!($args, input));
             output.refresh_screen()?;
-            match Reader.read_key()? {
+            let key = match Reader.read_event()? {
+                Event::Key(key) => key,
+                Event::Resize(columns, rows) => {
+                    output.resize(columns, rows);
+                    continue;
+                }
+                Event::Paste(text) => {
+                    input.extend(text.chars().filter(|c| !c.is_control()));
+                    continue;
+                }
+                _ => continue,
+            };
+            match key {
                 KeyEvent {
                     code: KeyCode::Enter,
                     modifiers: KeyModifiers::NONE,
+                    ..
                 } => {
-                    if !input.is_empty() {
+                    if $allow_empty || !input.is_empty() {
                         output.status_message.set_message(String::new());
-                        $callback(&input, KeyCode::Enter);
+                        $callback(&mut *output, &input, KeyCode::Enter);
+                        accepted = true;
                         break;
                     }
                 }
@@ -47,33 +141,43 @@ macro_rules! prompt {
                 } => {
                     output.status_message.set_message(String::new());
                     input.clear();
-                    $callback(&input, KeyCode::Esc);
+                    $callback(&mut *output, &input, KeyCode::Esc);
                     break;
                 }
                 KeyEvent {
                     code: KeyCode::Backspace | KeyCode::Delete,
                     modifiers: KeyModifiers::NONE,
+                    ..
                 } => {
                     input.pop();
+                    $callback(&mut *output, &input, KeyCode::Backspace)
                 }
                 KeyEvent {
-                    code: code @ (KeyCode::Char(..) | KeyCode::Tab),
+                    code: KeyCode::Tab,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => match $complete(&input) {
+                    Some(completed) => input = completed,
+                    None => {
+                        input.push('\t');
+                        $callback(&mut *output, &input, KeyCode::Tab)
+                    }
+                },
+                KeyEvent {
+                    code: code @ KeyCode::Char(ch),
                     modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                    ..
                 } => {
-                    input.push(match code {
-                        KeyCode::Tab => '\t',
-                        KeyCode::Char(ch) => ch,
-                        _ => unreachable!(),
-                    });
-                    $callback(&input, code)
+                    input.push(ch);
+                    $callback(&mut *output, &input, code)
                 }
-                KeyEvent { code, .. } => $callback(&input, code),
+                KeyEvent { code, .. } => $callback(&mut *output, &input, code),
             }
         }
-        if input.is_empty() {
-            None
-        } else {
+        if accepted {
             Some(input)
+        } else {
+            None
         }
     }};
 }
@@ -110,6 +214,29 @@ impl StatusMessage {
     }
 }
 
+/** 组成单词的字符：字母、数字（包括非 ASCII 的）和下划线 */
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/** 在 haystack 中查找 keyword，whole_word 时要求两侧都不是单词字符 */
+fn find_keyword(haystack: &str, keyword: &str, whole_word: bool) -> Option<usize> {
+    haystack
+        .match_indices(keyword)
+        .map(|(index, _)| index)
+        .find(|&index| {
+            !whole_word
+                || (!haystack[..index]
+                    .chars()
+                    .next_back()
+                    .is_some_and(is_word_char)
+                    && !haystack[index + keyword.len()..]
+                        .chars()
+                        .next()
+                        .is_some_and(is_word_char))
+        })
+}
+
 #[derive(Default)]
 struct Row {
     row_content: String,
@@ -137,7 +264,7 @@ impl Row {
 
     fn get_row_content_x(&self, render_x: usize) -> usize {
         let mut current_render_x = 0;
-        for (cursor_x, ch) in self.row_content.chars().enumerate() {
+        for (cursor_x, ch) in self.row_content.char_indices() {
             if ch == '\t' {
                 current_render_x += (TAB_STOP - 1) - (current_render_x % TAB_STOP);
             }
@@ -146,61 +273,387 @@ impl Row {
                 return cursor_x;
             }
         }
-        unreachable!("Invalid render_x")
+        // 超出行尾（例如鼠标点在行尾之后）时返回行尾
+        self.row_content.len()
+    }
+
+    fn get_render_x(&self, cursor_x: usize) -> usize {
+        self.row_content[..cursor_x].chars().fold(0, |render_x, c| {
+            if c == '\t' {
+                render_x + (TAB_STOP - 1) - (render_x % TAB_STOP) + 1
+            } else {
+                render_x + 1
+            }
+        })
+    }
+
+    /** 向后（forward）到下一个单词的末尾，或向前到上一个单词的开头 */
+    fn word_boundary(&self, at: usize, forward: bool) -> usize {
+        let content = &self.row_content;
+        if forward {
+            let mut chars = content[at..]
+                .char_indices()
+                .skip_while(|&(_, c)| !is_word_char(c));
+            chars
+                .find(|&(_, c)| !is_word_char(c))
+                .map_or(content.len(), |(index, _)| at + index)
+        } else {
+            content[..at]
+                .char_indices()
+                .rev()
+                .skip_while(|&(_, c)| !is_word_char(c))
+                .take_while(|&(_, c)| is_word_char(c))
+                .last()
+                .map_or(0, |(index, _)| index)
+        }
+    }
+
+    /** at 所在的单词范围；不在单词上时返回该字符本身 */
+    fn word_at(&self, at: usize) -> Range<usize> {
+        let content = &self.row_content;
+        let at = match content[at..].chars().next() {
+            Some(c) if is_word_char(c) => at,
+            // 光标在单词末尾时选中前面的单词
+            _ => match content[..at].chars().next_back() {
+                Some(c) if is_word_char(c) => at - c.len_utf8(),
+                _ => {
+                    let len = content[at..].chars().next().map_or(0, char::len_utf8);
+                    return at..at + len;
+                }
+            },
+        };
+        let start = content[..at]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| is_word_char(c))
+            .last()
+            .map_or(at, |(index, _)| index);
+        let end = content[at..]
+            .char_indices()
+            .find(|&(_, c)| !is_word_char(c))
+            .map_or(content.len(), |(index, _)| at + index);
+        start..end
     }
 }
 
 struct EditorRows {
     row_contents: Vec<Row>,
     filename: Option<PathBuf>,
+    /** 打开时检测到的编码，保存时默认按此编码写回 */
+    encoding: Encoding,
+    /** 二进制文件以十六进制模式编辑，此时 row_contents 为空 */
+    hex_view: Option<HexView>,
+    /** 大文件模式下 row_contents 只是当前载入的窗口 */
+    large_file: Option<LargeFile>,
+    /** 从文件内容推断出的缩进方式，为 None 时使用配置中的默认值 */
+    indent: Option<IndentStyle>,
+    marks: Marks,
+    /** 折叠的行，随增删的行一起移动 */
+    folds: Folds,
+    /** 浏览目录时为该目录，此时 row_contents 是只读的目录列表 */
+    directory: Option<PathBuf>,
+    /** 文件在 git 仓库中时，与 HEAD 版本的差异 */
+    git: Option<GitGutter>,
+    /** 记录撤销历史时为上次记录以来各次修改的反向修改，按发生的顺序排列 */
+    changes: Option<Vec<Edit>>,
 }
 
 impl EditorRows {
-    fn new() -> Self {
+    fn new() -> io::Result<Self> {
         match env::args().nth(1) {
-            None => Self {
+            None => Ok(Self {
                 row_contents: Vec::new(),
                 filename: None,
-            },
-            Some(file) => Self::from_file(file.into()),
+                encoding: Encoding::Utf8,
+                hex_view: None,
+                large_file: None,
+                indent: None,
+                marks: Marks::default(),
+                folds: Folds::default(),
+                directory: None,
+                git: None,
+                changes: None,
+            }),
+            Some(path) if fs::metadata(&path).is_ok_and(|it| it.is_dir()) => {
+                Self::from_directory(path.into())
+            }
+            Some(file) => Self::from_file(file.into(), None),
         }
     }
 
-    fn from_file(file: PathBuf) -> Self {
-        let file_contents = fs::read_to_string(&file).expect("Unable to read file");
-        Self {
+    /** 列出目录的内容 */
+    fn from_directory(dir: PathBuf) -> io::Result<Self> {
+        let dir = fs::canonicalize(dir)?;
+        Ok(Self {
+            row_contents: Self::rows_from_lines(browser::list(&dir)?),
+            filename: None,
+            encoding: Encoding::Utf8,
+            hex_view: None,
+            large_file: None,
+            indent: None,
+            marks: Marks::default(),
+            folds: Folds::default(),
+            directory: Some(dir),
+            git: None,
+            changes: None,
+        })
+    }
+
+    /** 读取文件，encoding 为 None 时自动检测编码 */
+    fn from_file(file: PathBuf, encoding: Option<Encoding>) -> io::Result<Self> {
+        let marks = Marks::load(&file);
+        if fs::metadata(&file).is_ok_and(|it| it.len() >= large_file::LARGE_FILE_THRESHOLD) {
+            // 大文件不做编码检测，默认按 UTF-8 读取，可以用 reopen 指定编码
+            let large_encoding = encoding.unwrap_or(Encoding::Utf8);
+            return Ok(Self {
+                row_contents: Vec::new(),
+                large_file: Some(LargeFile::open(&file, large_encoding)?),
+                filename: Some(file),
+                encoding: large_encoding,
+                hex_view: None,
+                indent: None,
+                marks,
+                folds: Folds::default(),
+                directory: None,
+                git: None,
+                changes: None,
+            });
+        }
+        let bytes = match fs::read(&file) {
+            Ok(bytes) => bytes,
+            // 文件不存在时当作新文件，保存时再创建
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let text = match encoding {
+            Some(encoding) => Some((
+                encoding,
+                encoding.decode(&bytes).ok_or_else(|| {
+                    io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("file is not valid {}", encoding.name()),
+                    )
+                })?,
+            )),
+            // 未指定编码时，二进制内容和无法按任何文本编码解码的内容用十六进制模式打开
+            None if HexView::is_binary(&bytes) => None,
+            None => Encoding::detect_text(&bytes)
+                .and_then(|encoding| Some((encoding, encoding.decode(&bytes)?))),
+        };
+        let (encoding, file_contents) = match text {
+            Some(text) => text,
+            None => {
+                return Ok(Self {
+                    row_contents: Vec::new(),
+                    filename: Some(file),
+                    // 切换回文本模式时使用的编码
+                    encoding: Encoding::detect(&bytes),
+                    hex_view: Some(HexView::new(bytes)),
+                    large_file: None,
+                    indent: None,
+                    marks,
+                    folds: Folds::default(),
+                    directory: None,
+                    git: None,
+                    changes: None,
+                });
+            }
+        };
+        let git = GitGutter::load(&file);
+        Ok(Self {
             filename: Some(file),
-            row_contents: file_contents
-                .lines()
-                .map(|it| {
-                    let mut row = Row::new(it.into(), String::new());
-                    Self::render_row(&mut row);
-                    row
-                })
-                .collect(),
+            row_contents: Self::rows_from(&file_contents),
+            encoding,
+            hex_view: None,
+            large_file: None,
+            indent: IndentStyle::detect(file_contents.lines()),
+            marks,
+            folds: Folds::default(),
+            directory: None,
+            git,
+            changes: None,
+        })
+    }
+
+    fn rows_from(contents: &str) -> Vec<Row> {
+        Self::rows_from_lines(contents.lines().map(String::from))
+    }
+
+    fn rows_from_lines(lines: impl IntoIterator<Item = String>) -> Vec<Row> {
+        lines
+            .into_iter()
+            .map(|it| {
+                let mut row = Row::new(it, String::new());
+                Self::render_row(&mut row);
+                row
+            })
+            .collect()
+    }
+
+    fn contents(&self) -> String {
+        self.row_contents
+            .iter()
+            .map(|it| it.row_content.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n")
+    }
+
+    /** 在文本和十六进制模式之间切换 */
+    fn toggle_hex(&mut self) -> io::Result<()> {
+        if self.large_file.is_some() {
+            return Err(io::Error::other(
+                "hex mode is not available for large files",
+            ));
         }
+        match self.hex_view.take() {
+            Some(hex_view) => {
+                // 保持原来的编码，修改后的字节不合法时留在十六进制模式
+                let contents = match self.encoding.decode(&hex_view.bytes) {
+                    Some(contents) => contents,
+                    None => {
+                        let encoding = self.encoding.name();
+                        self.hex_view = Some(hex_view);
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidData,
+                            format!("bytes are not valid {}", encoding),
+                        ));
+                    }
+                };
+                self.row_contents = Self::rows_from(&contents);
+                self.indent = IndentStyle::detect(contents.lines());
+                if let Some(git) = &mut self.git {
+                    git.invalidate();
+                }
+                // 十六进制模式中的修改可能增删了行，按前后各行的差异移动书签、跳转历史和折叠
+                if let Some(text_rows) = &hex_view.text_rows {
+                    let old: Vec<&str> = text_rows.iter().map(String::as_str).collect();
+                    let new: Vec<&str> = self
+                        .row_contents
+                        .iter()
+                        .map(|row| row.row_content.as_str())
+                        .collect();
+                    for hunk in diff::diff(&old, &new).into_iter().rev() {
+                        self.marks.rows_replaced(hunk.old.clone(), hunk.new.len());
+                        self.folds.rows_replaced(hunk.old, hunk.new.len());
+                    }
+                }
+            }
+            None => {
+                let bytes = self.encoding.encode(&self.contents())?;
+                let rows = self.row_contents.drain(..).map(|row| row.row_content);
+                let mut hex_view = HexView::new(bytes);
+                hex_view.text_rows = Some(rows.collect());
+                self.hex_view = Some(hex_view);
+            }
+        }
+        Ok(())
     }
 
     /** 总行数 */
     fn number_of_rows(&self) -> usize {
-        self.row_contents.len()
+        match &self.large_file {
+            Some(large_file) => large_file.number_of_rows(self.row_contents.len()),
+            None => self.row_contents.len(),
+        }
+    }
+
+    /** 大文件是否已扫描完，没有扫描完时 number_of_rows 只是目前已知的行数 */
+    fn is_fully_indexed(&self) -> bool {
+        self.large_file.as_ref().is_none_or(LargeFile::is_complete)
+    }
+
+    /** row_contents[0] 对应的行号 */
+    fn window_start(&self) -> usize {
+        self.large_file.as_ref().map_or(0, |it| it.window_start)
+    }
+
+    /**
+     * 修改 rows 中的行之前调用，修改后这些行变为 inserted 行。
+     * 大文件模式下标记窗口已修改；记录撤销历史时保存恢复原样所需的修改
+     */
+    fn before_edit(&mut self, rows: Range<usize>, inserted: usize) {
+        let window_start = self.window_start();
+        if let Some(large_file) = &mut self.large_file {
+            large_file.window_dirty = true;
+        }
+        if let Some(changes) = &mut self.changes {
+            changes.push(Edit {
+                range: rows.start..rows.start + inserted,
+                lines: self.row_contents[rows.start - window_start..rows.end - window_start]
+                    .iter()
+                    .map(|it| it.row_content.clone())
+                    .collect(),
+            });
+        }
+    }
+
+    /** 从后往前应用 edits，返回把内容恢复原样所需的修改 */
+    fn apply_edits(&mut self, edits: Vec<Edit>) -> Vec<Edit> {
+        let changes = self.changes.replace(Vec::new());
+        for edit in edits.into_iter().rev() {
+            self.replace_rows(edit.range, edit.lines);
+        }
+        std::mem::replace(&mut self.changes, changes).unwrap_or_default()
+    }
+
+    /** 大文件模式下至少扫描到第 rows 行，文件更短时扫描到末尾 */
+    fn index_to(&mut self, rows: usize) {
+        let window_len = self.row_contents.len();
+        if let Some(large_file) = &mut self.large_file {
+            large_file.index_to(rows, window_len);
+        }
+    }
+
+    /** 确保 around 前后 margin 行已经载入窗口 */
+    fn load_window(&mut self, around: usize, margin: usize) {
+        let window_len = self.row_contents.len();
+        let large_file = match &mut self.large_file {
+            Some(large_file) => large_file,
+            None => return,
+        };
+        large_file.index_to(around + margin, window_len);
+        let start = around.saturating_sub(margin);
+        let end = cmp::min(around + margin, large_file.number_of_rows(window_len));
+        let window_start = large_file.window_start;
+        if window_start <= start && end <= window_start + window_len && window_len > 0 {
+            return;
+        }
+        self.flush_window();
+        let large_file = self.large_file.as_mut().unwrap();
+        self.row_contents = Self::rows_from_lines(large_file.load_window(start..end));
+    }
+
+    /** 把窗口换出到 LargeFile 中，之后需要重新 load_window */
+    fn flush_window(&mut self) {
+        if let Some(large_file) = &mut self.large_file {
+            let rows = std::mem::take(&mut self.row_contents);
+            large_file.flush_window(rows.into_iter().map(|it| it.row_content).collect());
+        }
+    }
+
+    /** 大文件模式下查找，返回所在行号 */
+    fn find_large(&mut self, keyword: &str, whole_word: bool) -> Option<usize> {
+        self.flush_window();
+        self.large_file.as_mut()?.find(keyword, whole_word)
     }
 
     /** 获取某一行 */
     fn get_row(&self, at: usize) -> &str {
-        &self.row_contents[at].row_content
+        &self.row_contents[at - self.window_start()].row_content
     }
 
     fn get_render(&self, at: usize) -> &String {
-        &self.row_contents[at].render
+        &self.row_contents[at - self.window_start()].render
     }
 
     fn get_editor_row(&self, at: usize) -> &Row {
-        &self.row_contents[at]
+        &self.row_contents[at - self.window_start()]
     }
 
     fn get_editor_row_mut(&mut self, at: usize) -> &mut Row {
-        &mut self.row_contents[at]
+        self.before_edit(at..at + 1, 1);
+        let window_start = self.window_start();
+        &mut self.row_contents[at - window_start]
     }
 
     fn render_row(row: &mut Row) {
@@ -227,31 +680,133 @@ impl EditorRows {
     fn insert_row(&mut self, at: usize, contents: String) {
         let mut new_row = Row::new(contents, String::new());
         EditorRows::render_row(&mut new_row);
-        self.row_contents.insert(at, new_row);
+        self.before_edit(at..at, 1);
+        let window_start = self.window_start();
+        self.row_contents.insert(at - window_start, new_row);
+        self.marks.rows_inserted(at, 1);
+        self.folds.rows_inserted(at, 1);
     }
 
     /** 保存到本地 */
     fn save(&mut self) -> io::Result<usize> {
         match &self.filename {
-            None => Err(io::Error::new(ErrorKind::Other, "no file name specified")),
+            None => Err(io::Error::other("no file name specified")),
+            Some(name) if self.large_file.is_some() => {
+                let name = name.clone();
+                let window = self.window_start()..self.window_start() + self.row_contents.len();
+                self.flush_window();
+                let encoding = self.encoding;
+                let len = self.large_file.as_mut().unwrap().save(&name, encoding)?;
+                // 保存后重新映射新文件，窗口内容与新文件一致
+                let mut large_file = LargeFile::open(&name, encoding)?;
+                large_file.index_to(window.end, 0);
+                self.row_contents = Self::rows_from_lines(large_file.load_window(window));
+                self.large_file = Some(large_file);
+                Ok(len)
+            }
             Some(name) => {
-                let mut file = fs::OpenOptions::new().write(true).create(true).open(name)?;
-                let contents: String = self
-                    .row_contents
-                    .iter()
-                    .map(|it| it.row_content.as_str())
-                    .collect::<Vec<&str>>()
-                    .join("\n");
-                file.set_len(contents.len() as u64)?;
-                file.write_all(contents.as_bytes())?;
-                Ok(contents.as_bytes().len())
+                // 先编码再打开文件，避免编码失败时把原文件截断
+                let bytes = match &self.hex_view {
+                    Some(hex_view) => hex_view.bytes.clone(),
+                    None => self.encoding.encode(&self.contents())?,
+                };
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(name)?;
+                file.write_all(&bytes)?;
+                Ok(bytes.len())
+            }
+        }
+    }
+
+    fn file_type(&self) -> &'static FileType {
+        FileType::from_path(self.filename.as_deref())
+    }
+
+    /** 在 (x, y) 处插入可能包含多行的文本，返回插入结束的位置 */
+    fn insert_text(&mut self, x: usize, y: usize, text: &str) -> (usize, usize) {
+        if y == self.number_of_rows() {
+            self.insert_row(y, String::new());
+        }
+        let mut lines = text.split('\n');
+        let first = lines.next().unwrap_or_default();
+        let rest: Vec<&str> = lines.collect();
+        let row = self.get_editor_row_mut(y);
+        let tail = row.row_content.split_off(x);
+        row.row_content.push_str(first);
+        let end = match rest.last() {
+            None => {
+                row.row_content.push_str(&tail);
+                (x + first.len(), y)
             }
+            Some(last) => (last.len(), y + rest.len()),
+        };
+        Self::render_row(row);
+        if let Some((last, middle)) = rest.split_last() {
+            let new_rows = Self::rows_from_lines(
+                middle
+                    .iter()
+                    .map(|it| it.to_string())
+                    .chain(Some(format!("{}{}", last, tail))),
+            );
+            self.before_edit(y + 1..y + 1, rest.len());
+            let at = y + 1 - self.window_start();
+            self.row_contents.splice(at..at, new_rows);
+            self.marks.rows_inserted(y + 1, rest.len());
+            self.folds.rows_inserted(y + 1, rest.len());
+        }
+        end
+    }
+
+    fn remove_rows(&mut self, rows: Range<usize>) {
+        self.before_edit(rows.clone(), 0);
+        let window_start = self.window_start();
+        self.row_contents
+            .drain(rows.start - window_start..rows.end - window_start);
+        self.marks.rows_removed(rows.clone());
+        self.folds.rows_removed(rows);
+    }
+
+    /** 大文件模式下该行是否已载入窗口 */
+    fn is_loaded(&self, at: usize) -> bool {
+        let window_start = self.window_start();
+        at >= window_start && at < window_start + self.row_contents.len()
+    }
+
+    /** 用 lines 替换 rows 中的各行 */
+    fn replace_rows(&mut self, rows: Range<usize>, lines: Vec<String>) {
+        self.before_edit(rows.clone(), lines.len());
+        let window_start = self.window_start();
+        self.marks.rows_replaced(rows.clone(), lines.len());
+        self.folds.rows_replaced(rows.clone(), lines.len());
+        self.row_contents.splice(
+            rows.start - window_start..rows.end - window_start,
+            Self::rows_from_lines(lines),
+        );
+    }
+
+    /** 把 rows 中的第一行移到末尾（up）或最后一行移到开头 */
+    fn rotate_rows(&mut self, rows: Range<usize>, up: bool) {
+        self.before_edit(rows.clone(), rows.len());
+        let window_start = self.window_start();
+        self.marks.rows_rotated(rows.clone(), up);
+        self.folds.rows_rotated(rows.clone());
+        let rows = &mut self.row_contents[rows.start - window_start..rows.end - window_start];
+        if up {
+            rows.rotate_left(1);
+        } else {
+            rows.rotate_right(1);
         }
     }
 
     /** 连接相邻行（当进行删除操作影响多行时，出触发此函数） */
     fn join_adjacent_rows(&mut self, at: usize) {
-        let current_row = self.row_contents.remove(at);
+        self.before_edit(at..at + 1, 0);
+        let current_row = self.row_contents.remove(at - self.window_start());
+        self.marks.row_joined(at);
+        self.folds.row_joined(at);
         let previous_row = self.get_editor_row_mut(at - 1);
         previous_row.row_content.push_str(&current_row.row_content);
         Self::render_row(previous_row);
@@ -268,6 +823,16 @@ struct CursorController {
     /** 水平滚动到某一行 */
     column_offset: usize,
     render_x: usize,
+    /** 选区的另一端 (x, y)，光标是选区的活动端 */
+    selection_anchor: Option<(usize, usize)>,
+    /** 滚轮滚动后视图不再跟随光标，直到下一次按键 */
+    free_scroll: bool,
+    /** 除 (cursor_x, cursor_y) 之外的其他光标 */
+    extra_cursors: Vec<(usize, usize)>,
+    /** 列选择的另一角 (x, y)，选中的是两角之间的矩形（按显示列） */
+    column_anchor: Option<(usize, usize)>,
+    /** 列选择时光标一角的显示列，可以超出行尾 */
+    column_x: usize,
 }
 
 impl CursorController {
@@ -280,29 +845,64 @@ impl CursorController {
             row_offset: 0, // 默认滚动到首行
             column_offset: 0,
             render_x: 0,
+            selection_anchor: None,
+            free_scroll: false,
+            extra_cursors: Vec::new(),
+            column_anchor: None,
+            column_x: 0,
         }
     }
 
     fn get_render_x(&self, row: &Row) -> usize {
-        row.row_content[..self.cursor_x]
-            .chars()
-            .fold(0, |render_x, c| {
-                if c == '\t' {
-                    render_x + (TAB_STOP - 1) - (render_x % TAB_STOP) + 1
-                } else {
-                    render_x + 1
-                }
-            })
+        row.get_render_x(self.cursor_x)
     }
 
-    fn scroll(&mut self, editor_rows: &EditorRows) {
+    /** 选区的起点和终点 ((x, y), (x, y))，按文本顺序排列 */
+    fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let anchor = self.selection_anchor?;
+        let cursor = (self.cursor_x, self.cursor_y);
+        match (anchor.1, anchor.0).cmp(&(cursor.1, cursor.0)) {
+            Ordering::Less => Some((anchor, cursor)),
+            Ordering::Greater => Some((cursor, anchor)),
+            Ordering::Equal => None,
+        }
+    }
+
+    fn scroll(&mut self, editor_rows: &mut EditorRows) {
+        // 滚轮滚动时不跟随光标
+        if self.free_scroll {
+            return;
+        }
         self.render_x = 0;
         if self.cursor_y < editor_rows.number_of_rows() {
             self.render_x = self.get_render_x(editor_rows.get_editor_row(self.cursor_y));
         }
+        if self.column_anchor.is_some() {
+            self.render_x = self.column_x;
+        }
+        // 光标被移到折叠的行中（例如查找）时展开折叠
+        if editor_rows.folds.is_hidden(self.cursor_y) {
+            editor_rows.folds.reveal(self.cursor_y);
+        }
+        if editor_rows.folds.is_hidden(self.row_offset) {
+            self.row_offset = editor_rows
+                .folds
+                .previous_visible(self.row_offset + 1)
+                .unwrap();
+        }
         self.row_offset = cmp::min(self.row_offset, self.cursor_y);
-        if self.cursor_y >= self.row_offset + self.screen_rows {
-            self.row_offset = self.cursor_y - self.screen_rows + 1;
+        if editor_rows
+            .folds
+            .visible_between(self.row_offset, self.cursor_y)
+            >= self.screen_rows
+        {
+            self.row_offset = self.cursor_y;
+            for _ in 1..self.screen_rows {
+                match editor_rows.folds.previous_visible(self.row_offset) {
+                    Some(previous) => self.row_offset = previous,
+                    None => break,
+                }
+            }
         }
         self.column_offset = cmp::min(self.column_offset, self.render_x);
         if self.render_x >= self.column_offset + self.screen_columns {
@@ -310,34 +910,78 @@ impl CursorController {
         }
     }
 
+    /** 按住 Shift 移动时扩展选区，否则取消选区 */
+    fn update_selection(&mut self, extend: bool) {
+        self.column_anchor = None;
+        if extend {
+            self.selection_anchor
+                .get_or_insert((self.cursor_x, self.cursor_y));
+        } else {
+            self.selection_anchor = None;
+        }
+    }
+
+    /** 按单词移动，在行首或行尾时移动到相邻的行 */
+    fn move_word(&mut self, forward: bool, editor_rows: &EditorRows) {
+        if self.cursor_y >= editor_rows.number_of_rows() {
+            return self.move_cursor(KeyCode::Left, editor_rows);
+        }
+        let row = editor_rows.get_editor_row(self.cursor_y);
+        match forward {
+            true if self.cursor_x == row.row_content.len() => {
+                self.move_cursor(KeyCode::Right, editor_rows)
+            }
+            false if self.cursor_x == 0 => self.move_cursor(KeyCode::Left, editor_rows),
+            _ => self.cursor_x = row.word_boundary(self.cursor_x, forward),
+        }
+    }
+
     fn move_cursor(&mut self, direction: KeyCode, editor_rows: &EditorRows) {
         let number_of_rows = editor_rows.number_of_rows();
 
         match direction {
             KeyCode::Up => {
-                self.cursor_y = self.cursor_y.saturating_sub(1);
+                self.cursor_y = editor_rows
+                    .folds
+                    .previous_visible(self.cursor_y)
+                    .unwrap_or(0);
             }
             KeyCode::Left => {
                 if self.cursor_x != 0 {
-                    self.cursor_x -= 1;
+                    self.cursor_x -= editor_rows.get_row(self.cursor_y)[..self.cursor_x]
+                        .chars()
+                        .next_back()
+                        .map_or(1, char::len_utf8);
                 } else if self.cursor_y > 0 {
                     // 这里是当在行首时按 left 键后移动到上一行的末尾
-                    self.cursor_y -= 1;
+                    self.cursor_y = editor_rows.folds.previous_visible(self.cursor_y).unwrap();
                     self.cursor_x = editor_rows.get_row(self.cursor_y).len();
                 }
             }
             KeyCode::Down => {
                 if self.cursor_y < number_of_rows {
-                    self.cursor_y += 1;
+                    self.cursor_y = cmp::min(
+                        editor_rows.folds.next_visible(self.cursor_y),
+                        number_of_rows,
+                    );
                 }
             }
             KeyCode::Right => {
                 if self.cursor_y < number_of_rows {
                     // 当在行尾时按 Right 后，移动到下一行的行首
-                    match self.cursor_x.cmp(&editor_rows.get_row(self.cursor_y).len()) {
-                        Ordering::Less => self.cursor_x += 1,
+                    let row = editor_rows.get_row(self.cursor_y);
+                    match self.cursor_x.cmp(&row.len()) {
+                        Ordering::Less => {
+                            self.cursor_x += row[self.cursor_x..]
+                                .chars()
+                                .next()
+                                .map_or(1, char::len_utf8)
+                        }
                         Ordering::Equal => {
-                            self.cursor_y += 1;
+                            self.cursor_y = cmp::min(
+                                editor_rows.folds.next_visible(self.cursor_y),
+                                number_of_rows,
+                            );
                             self.cursor_x = 0
                         }
                         _ => {}
@@ -352,12 +996,16 @@ impl CursorController {
             KeyCode::Home => self.cursor_x = 0,
             _ => unimplemented!(),
         }
-        let row_len = if self.cursor_y < number_of_rows {
-            editor_rows.get_row(self.cursor_y).len()
+        let row = if self.cursor_y < number_of_rows {
+            editor_rows.get_row(self.cursor_y)
         } else {
-            0
+            ""
         };
-        self.cursor_x = cmp::min(self.cursor_x, row_len);
+        self.cursor_x = cmp::min(self.cursor_x, row.len());
+        // 上下移动后可能落在多字节字符中间，退回到字符边界
+        while !row.is_char_boundary(self.cursor_x) {
+            self.cursor_x -= 1;
+        }
     }
 }
 
@@ -400,148 +1048,2301 @@ impl io::Write for EditorContents {
     }
 }
 
+/** Ctrl-C 复制的内容，列选择复制的是各行中被选中的部分 */
+enum Clipboard {
+    Text(String),
+    Block(Vec<String>),
+}
+
 struct Output {
+    /** 文本区域的大小，不含状态栏和消息栏 */
     win_size: (usize, usize),
+    /** 屏幕底部显示的状态栏、消息栏数量，终端太矮时会减少 */
+    bars: usize,
     editor_contents: EditorContents,
     cursor_controller: CursorController,
     editor_rows: EditorRows,
     status_message: StatusMessage,
     dirty: u64, // 是否在打开后或保存文件后被修改，脏数据，每更改文本后递增
+    config: Config,
+    /** 上一次鼠标左键按下的时间和位置，用来识别双击 */
+    last_click: Option<(Instant, u16, u16)>,
+    clipboard: Option<Clipboard>,
+    /** 输入提示时显示的候选列表 */
+    popup: Option<Popup>,
+    /** 最近一次项目搜索，关闭结果列表后仍保留，可以再次打开 */
+    project_search: Option<ProjectSearch>,
+    history: History,
 }
 
 impl Output {
-    fn new() -> Self {
-        let win_size = terminal::size()
-            .map(|(x, y)| (x as usize, y as usize - 2))
-            .unwrap();
-        Self {
+    fn new() -> crossterm::Result<Self> {
+        let (columns, rows) = terminal::size()?;
+        let (win_size, bars) = Self::layout(columns, rows);
+        Ok(Self {
             win_size,
+            bars,
             editor_contents: EditorContents::new(),
             cursor_controller: CursorController::new(win_size),
-            editor_rows: EditorRows::new(),
+            editor_rows: EditorRows::new()?,
             status_message: StatusMessage::new(
-                "HELP: Ctrl-S = Save | Ctrl-Q = Quit | Ctrl-F = Find".into(),
+                "HELP: Ctrl-S = Save | Ctrl-Q = Quit | Ctrl-F = Find | Ctrl-O = Open | Ctrl-E = Command".into(),
             ), //modify
             dirty: 0,
+            config: Config::load(),
+            last_click: None,
+            clipboard: None,
+            popup: None,
+            project_search: None,
+            history: History::default(),
+        })
+    }
+
+    fn apply_mouse_capture(&self) -> crossterm::Result<()> {
+        if self.config.mouse {
+            execute!(stdout(), EnableMouseCapture)
+        } else {
+            execute!(stdout(), DisableMouseCapture)
         }
     }
 
-    fn clear_screen() -> crossterm::Result<()> {
-        execute!(stdout(), terminal::Clear(ClearType::All))?;
-        execute!(stdout(), cursor::MoveTo(0, 0))
+    /** 把屏幕坐标转换为缓冲区中的位置 (x, y) */
+    fn screen_to_buffer(&self, column: u16, row: u16) -> (usize, usize) {
+        let number_of_rows = self.editor_rows.number_of_rows();
+        if number_of_rows == 0 {
+            return (0, 0);
+        }
+        let row = cmp::min(row as usize, self.win_size.1 - 1);
+        let folds = &self.editor_rows.folds;
+        let y = (0..row).fold(self.cursor_controller.row_offset, |y, _| {
+            folds.next_visible(y)
+        });
+        let y = cmp::min(y, number_of_rows - 1);
+        let x = self.editor_rows.get_editor_row(y).get_row_content_x(
+            (column as usize).saturating_sub(self.gutter_width())
+                + self.cursor_controller.column_offset,
+        );
+        (x, y)
     }
 
-    /* modify */
-    fn find(&mut self) -> io::Result<()> {
-        if let Some(keyword) = prompt!(self, "Search: {} (ESC to cancel)") {
-            for i in 0..self.editor_rows.number_of_rows() {
-                let row = self.editor_rows.get_editor_row(i);
-                if let Some(index) = row.render.find(&keyword) {
-                    self.cursor_controller.cursor_y = i;
-                    self.cursor_controller.cursor_x = row.get_row_content_x(index);
-                    self.cursor_controller.row_offset = self.editor_rows.number_of_rows();
-                    break;
+    fn handle_mouse(&mut self, event: MouseEvent) {
+        if self.editor_rows.hex_view.is_some() {
+            return;
+        }
+        match event.kind {
+            MouseEventKind::ScrollDown => {
+                let last_row = self.editor_rows.number_of_rows().saturating_sub(1);
+                let cursor_controller = &mut self.cursor_controller;
+                for _ in 0..WHEEL_STEP {
+                    let next = self
+                        .editor_rows
+                        .folds
+                        .next_visible(cursor_controller.row_offset);
+                    if next > last_row {
+                        break;
+                    }
+                    cursor_controller.row_offset = next;
+                }
+                self.cursor_controller.free_scroll = true;
+            }
+            MouseEventKind::ScrollUp => {
+                let cursor_controller = &mut self.cursor_controller;
+                for _ in 0..WHEEL_STEP {
+                    if let Some(previous) = self
+                        .editor_rows
+                        .folds
+                        .previous_visible(cursor_controller.row_offset)
+                    {
+                        cursor_controller.row_offset = previous;
+                    }
+                }
+                self.cursor_controller.free_scroll = true;
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                let (x, y) = self.screen_to_buffer(event.column, event.row);
+                self.cursor_controller.free_scroll = false;
+                self.cursor_controller.extra_cursors.clear();
+                self.cursor_controller.column_anchor = None;
+                self.cursor_controller.cursor_x = x;
+                self.cursor_controller.cursor_y = y;
+                let double_click = matches!(self.last_click,
+                    Some((time, column, row)) if time.elapsed() < DOUBLE_CLICK
+                        && (column, row) == (event.column, event.row));
+                if double_click && y < self.editor_rows.number_of_rows() {
+                    let word = self.editor_rows.get_editor_row(y).word_at(x);
+                    self.cursor_controller.selection_anchor = Some((word.start, y));
+                    self.cursor_controller.cursor_x = word.end;
+                    self.last_click = None;
+                } else {
+                    self.cursor_controller.selection_anchor = Some((x, y));
+                    self.last_click = Some((Instant::now(), event.column, event.row));
                 }
             }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                let (x, y) = self.screen_to_buffer(event.column, event.row);
+                self.cursor_controller.cursor_x = x;
+                self.cursor_controller.cursor_y = y;
+            }
+            MouseEventKind::Up(MouseButton::Left)
+                if self.cursor_controller.selection().is_none() =>
+            {
+                self.cursor_controller.selection_anchor = None;
+            }
+            _ => {}
         }
-        Ok(())
     }
 
-    fn draw_message_bar(&mut self) {
-        queue!(
-            self.editor_contents,
-            terminal::Clear(ClearType::UntilNewLine)
-        )
-        .unwrap();
-        if let Some(msg) = self.status_message.message() {
-            self.editor_contents
-                .push_str(&msg[..cmp::min(self.win_size.0, msg.len())]);
+    /** 按键后视图重新跟随光标，大文件模式下需要先载入光标附近的行 */
+    fn follow_cursor(&mut self) {
+        if self.cursor_controller.free_scroll {
+            self.cursor_controller.free_scroll = false;
+            self.editor_rows
+                .load_window(self.cursor_controller.cursor_y, self.window_margin());
         }
     }
 
-    fn delete_char(&mut self) {
-        if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
-            return;
-        }
-        if self.cursor_controller.cursor_y == 0 && self.cursor_controller.cursor_x == 0 {
+    /** 粘贴的内容作为一次修改整体插入，不经过逐字符的编辑逻辑 */
+    fn paste(&mut self, text: &str) {
+        if self.editor_rows.hex_view.is_some() {
+            self.status_message
+                .set_message("Paste is not supported in hex mode".into());
             return;
         }
-        let row = self
-            .editor_rows
-            .get_editor_row_mut(self.cursor_controller.cursor_y);
-        if self.cursor_controller.cursor_x > 0 {
-            row.delete_char(self.cursor_controller.cursor_x - 1);
-            self.cursor_controller.cursor_x -= 1;
-        } else {
-            let previous_row_content = self
-                .editor_rows
-                .get_row(self.cursor_controller.cursor_y - 1);
-            self.cursor_controller.cursor_x = previous_row_content.len();
-            self.editor_rows
-                .join_adjacent_rows(self.cursor_controller.cursor_y);
-            self.cursor_controller.cursor_y -= 1;
-        }
+        self.delete_selection();
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let (x, y) = self.editor_rows.insert_text(
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+            &text,
+        );
+        self.cursor_controller.cursor_x = x;
+        self.cursor_controller.cursor_y = y;
         self.dirty += 1;
     }
 
-    fn insert_newline(&mut self) {
-        if self.cursor_controller.cursor_x == 0 {
-            self.editor_rows
-                .insert_row(self.cursor_controller.cursor_y, String::new())
-        } else {
-            // 当在某行中按下 enter 键，则会新建一行并显示之后的内容
-            let current_row = self
-                .editor_rows
-                .get_editor_row_mut(self.cursor_controller.cursor_y);
-            let new_row_content = current_row.row_content[self.cursor_controller.cursor_x..].into();
-            current_row
-                .row_content
-                .truncate(self.cursor_controller.cursor_x);
-            EditorRows::render_row(current_row);
-            self.editor_rows
-                .insert_row(self.cursor_controller.cursor_y + 1, new_row_content);
+    /** 删除选中的文本，没有选区时返回 false */
+    fn delete_selection(&mut self) -> bool {
+        if self.cursor_controller.column_anchor.is_some() {
+            self.delete_column();
+            return true;
         }
-        self.cursor_controller.cursor_x = 0;
-        self.cursor_controller.cursor_y += 1;
+        let selection = self.cursor_controller.selection();
+        self.cursor_controller.selection_anchor = None;
+        let ((start_x, start_y), (mut end_x, mut end_y)) = match selection {
+            Some(selection) => selection,
+            None => return false,
+        };
+        let number_of_rows = self.editor_rows.number_of_rows();
+        if end_y >= number_of_rows {
+            // 光标在最后一行之后，相当于选到最后一行的行尾
+            end_y = number_of_rows - 1;
+            end_x = self.editor_rows.get_row(end_y).len();
+        }
+        if !self.rows_loaded(start_y..end_y + 1) {
+            return true;
+        }
+        let tail = self.editor_rows.get_row(end_y)[end_x..].to_string();
+        let row = self.editor_rows.get_editor_row_mut(start_y);
+        row.row_content.truncate(start_x);
+        row.row_content.push_str(&tail);
+        EditorRows::render_row(row);
+        self.editor_rows.remove_rows(start_y + 1..end_y + 1);
+        self.cursor_controller.cursor_x = start_x;
+        self.cursor_controller.cursor_y = start_y;
         self.dirty += 1;
+        true
     }
 
-    fn insert_char(&mut self, ch: char) {
-        if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
-            self.editor_rows
-                .insert_row(self.editor_rows.number_of_rows(), String::new());
-            self.dirty += 1;
+    /** 根据终端大小计算文本区域，至少保留一行文本，剩下的行依次给状态栏和消息栏 */
+    fn layout(columns: u16, rows: u16) -> ((usize, usize), usize) {
+        let rows = rows as usize;
+        let bars = cmp::min(rows.saturating_sub(1), 2);
+        ((columns as usize, cmp::max(rows - bars, 1)), bars)
+    }
+
+    fn resize(&mut self, columns: u16, rows: u16) {
+        let (win_size, bars) = Self::layout(columns, rows);
+        self.win_size = win_size;
+        self.bars = bars;
+        self.cursor_controller.screen_columns = win_size.0;
+        self.cursor_controller.screen_rows = win_size.1;
+    }
+
+    /** 换行，屏幕最后一行之后不能再输出换行，否则终端会向上滚动 */
+    fn line_break(&mut self, screen_row: usize) {
+        if screen_row + 1 < self.win_size.1 + self.bars {
+            self.editor_contents.push_str("\r\n");
         }
-        self.editor_rows
-            .get_editor_row_mut(self.cursor_controller.cursor_y)
-            .insert_char(self.cursor_controller.cursor_x, ch);
-        self.cursor_controller.cursor_x += 1;
-        self.dirty += 1;
     }
 
-    /** 显示状态栏 */
-    fn draw_status_bar(&mut self) {
-        self.editor_contents
-            .push_str(&style::Attribute::Reverse.to_string());
-        let info = format!(
-            "{} {} -- {} lines",
-            self.editor_rows
-                .filename
-                .as_ref()
-                .and_then(|path| path.file_name())
-                .and_then(|name| name.to_str())
-                .unwrap_or("[No Name]"),
-            if self.dirty > 0 { "(modified)" } else { "" },
-            self.editor_rows.number_of_rows()
-        );
-        let info_len = cmp::min(info.len(), self.win_size.0);
-        let line_info = format!(
-            "{}/{}",
-            self.cursor_controller.cursor_y + 1,
-            self.editor_rows.number_of_rows()
-        );
-        self.editor_contents.push_str(&info[..info_len]);
-        for i in info_len..self.win_size.0 {
+    fn clear_screen() -> crossterm::Result<()> {
+        execute!(stdout(), terminal::Clear(ClearType::All))?;
+        execute!(stdout(), cursor::MoveTo(0, 0))
+    }
+
+    /* modify */
+    fn find(&mut self) -> io::Result<()> {
+        let whole_word = self.config.whole_word;
+        let keyword = if whole_word {
+            prompt!(self, "Search (whole word): {} (ESC to cancel)")
+        } else {
+            prompt!(self, "Search: {} (ESC to cancel)")
+        };
+        if let Some(keyword) = keyword {
+            let found = if self.editor_rows.large_file.is_some() {
+                let margin = self.window_margin();
+                self.editor_rows
+                    .find_large(&keyword, whole_word)
+                    .inspect(|&i| self.editor_rows.load_window(i, margin))
+            } else {
+                (0..self.editor_rows.number_of_rows()).find(|&i| {
+                    find_keyword(self.editor_rows.get_render(i), &keyword, whole_word).is_some()
+                })
+            };
+            if let Some(i) = found {
+                self.record_jump();
+                let row = self.editor_rows.get_editor_row(i);
+                self.cursor_controller.cursor_y = i;
+                self.cursor_controller.cursor_x = find_keyword(&row.render, &keyword, whole_word)
+                    .map_or(0, |index| {
+                        row.get_row_content_x(row.render[..index].chars().count())
+                    });
+                self.cursor_controller.row_offset = self.editor_rows.number_of_rows();
+            }
+        }
+        Ok(())
+    }
+
+    /** 十六进制模式下的按键处理，返回 false 表示交给普通按键处理（保存、退出等） */
+    fn hex_keypress(&mut self, key: KeyEvent) -> io::Result<bool> {
+        let screen_rows = self.win_size.1;
+        let hex_view = match &mut self.editor_rows.hex_view {
+            Some(hex_view) => hex_view,
+            None => return Ok(false),
+        };
+        match key {
+            KeyEvent {
+                code:
+                    direction @ (KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Home
+                    | KeyCode::End
+                    | KeyCode::PageUp
+                    | KeyCode::PageDown),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => hex_view.move_cursor(direction, screen_rows),
+            KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => {
+                if let Some(digit) = ch.to_digit(16) {
+                    if hex_view.overwrite_nibble(digit as u8) {
+                        self.dirty += 1;
+                    }
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char('g'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                if let Some(offset) = prompt!(self, "Goto offset (hex): {} (ESC to cancel)") {
+                    let hex_view = self.editor_rows.hex_view.as_mut().unwrap();
+                    let offset = offset.trim().trim_start_matches("0x");
+                    match usize::from_str_radix(offset, 16) {
+                        Ok(offset) if hex_view.goto(offset) => {}
+                        _ => self
+                            .status_message
+                            .set_message(format!("Invalid offset: {}", offset)),
+                    }
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char('f'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                if let Some(pattern) = prompt!(self, "Search bytes: {} (ESC to cancel)") {
+                    let hex_view = self.editor_rows.hex_view.as_mut().unwrap();
+                    match HexView::parse_pattern(&pattern) {
+                        Some(bytes) => match hex_view.find(&bytes) {
+                            Some(offset) => {
+                                hex_view.goto(offset);
+                            }
+                            None => self
+                                .status_message
+                                .set_message(format!("Not found: {}", pattern)),
+                        },
+                        None => self
+                            .status_message
+                            .set_message(format!("Invalid byte pattern: {}", pattern)),
+                    }
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Enter | KeyCode::Tab | KeyCode::Backspace | KeyCode::Delete,
+                ..
+            } => {}
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn cursor_position(&self) -> (usize, usize) {
+        (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        )
+    }
+
+    /** 十六进制、大文件和目录浏览模式下不记录撤销历史 */
+    fn history_available(&self) -> bool {
+        let editor_rows = &self.editor_rows;
+        editor_rows.hex_view.is_none()
+            && editor_rows.large_file.is_none()
+            && editor_rows.directory.is_none()
+    }
+
+    /** 处理每个事件之前调用，还没有开始记录时开始记录之后的修改 */
+    fn start_history(&mut self) {
+        if self.history_available() && self.editor_rows.changes.is_none() {
+            self.editor_rows.changes = Some(Vec::new());
+        }
+    }
+
+    /**
+     * 处理每个事件之后调用。changed 表示这次事件修改了内容，cursor 为事件之前的光标位置；
+     * 连续输入的字符（typing）合并为一步，遇到其他事件时再记录
+     */
+    fn record_history(&mut self, changed: bool, typing: bool, cursor: (usize, usize)) {
+        if !self.history_available() {
+            self.history.clear();
+            self.editor_rows.changes = None;
+            return;
+        }
+        if changed {
+            self.history.touch(cursor);
+        }
+        if !(changed && typing) {
+            self.commit_history();
+        }
+    }
+
+    fn commit_history(&mut self) {
+        if let Some(changes) = &mut self.editor_rows.changes {
+            let edits = std::mem::take(changes);
+            let cursor = (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            );
+            self.history.commit(edits, cursor);
+        }
+    }
+
+    /**
+     * 大文件的窗口中有无法解码的行时，开始记录这次事件的修改，
+     * 返回窗口原来是否已被修改，供 revert_undecodable 恢复
+     */
+    fn protect_undecodable(&mut self) -> Option<bool> {
+        let large_file = self.editor_rows.large_file.as_ref()?;
+        large_file.window_undecodable?;
+        let window_dirty = large_file.window_dirty;
+        self.editor_rows.changes = Some(Vec::new());
+        Some(window_dirty)
+    }
+
+    /** 撤销这次事件对无法解码的窗口所做的修改，保存时这些行会被有损解码的内容覆盖 */
+    fn revert_undecodable(&mut self, window_dirty: bool, dirty: u64, cursor: (usize, usize)) {
+        let changes = self.editor_rows.changes.take().unwrap_or_default();
+        if changes.is_empty() {
+            return;
+        }
+        self.editor_rows.apply_edits(changes);
+        let large_file = match &mut self.editor_rows.large_file {
+            Some(large_file) => large_file,
+            None => return,
+        };
+        large_file.window_dirty = window_dirty;
+        let line = large_file.window_undecodable.unwrap_or_default();
+        self.dirty = dirty;
+        self.cursor_controller.selection_anchor = None;
+        (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        ) = cursor;
+        self.status_message.set_message(format!(
+            "Line {} is not valid {}, nearby lines cannot be edited",
+            line + 1,
+            self.editor_rows.encoding.name()
+        ));
+    }
+
+    /** 撤销上一步修改，redo 为 true 时重做上一次撤销的修改 */
+    fn undo(&mut self, redo: bool) {
+        if self.editor_rows.changes.is_none() {
+            self.status_message
+                .set_message("Undo is not available in hex, large file or browser mode".into());
+            return;
+        }
+        self.commit_history();
+        let editor_rows = &mut self.editor_rows;
+        let apply = |edits| editor_rows.apply_edits(edits);
+        let cursor = if redo {
+            self.history.redo(apply)
+        } else {
+            self.history.undo(apply)
+        };
+        match cursor {
+            Some(cursor) => {
+                self.dirty += 1;
+                self.cursor_controller.extra_cursors.clear();
+                self.go_to(cursor);
+            }
+            None => self.status_message.set_message(
+                if redo {
+                    "Nothing to redo"
+                } else {
+                    "Nothing to undo"
+                }
+                .into(),
+            ),
+        }
+    }
+
+    /** 换成另一个缓冲区，光标、选区和折叠都回到初始状态 */
+    fn set_buffer(&mut self, editor_rows: EditorRows) {
+        self.editor_rows = editor_rows;
+        self.cursor_controller = CursorController::new(self.win_size);
+        self.dirty = 0;
+        self.history.clear();
+    }
+
+    /** 打开文件或目录，当前缓冲区有未保存的修改时拒绝 */
+    fn open_path(&mut self, path: PathBuf) -> bool {
+        if self.dirty > 0 {
+            self.status_message
+                .set_message("File has unsaved changes, save it first".into());
+            return false;
+        }
+        let editor_rows = if path.is_dir() {
+            EditorRows::from_directory(path)
+        } else {
+            EditorRows::from_file(path, None)
+        };
+        match editor_rows {
+            Ok(editor_rows) => {
+                self.set_buffer(editor_rows);
+                true
+            }
+            Err(err) => {
+                self.status_message.set_message(err.to_string());
+                false
+            }
+        }
+    }
+
+    /** 重新列出目录，并把光标移到名为 entry 的一行 */
+    fn browse(&mut self, dir: PathBuf, entry: Option<&str>) {
+        if !self.open_path(dir) {
+            return;
+        }
+        if let Some(y) = entry.and_then(|entry| {
+            (0..self.editor_rows.number_of_rows()).find(|&y| self.editor_rows.get_row(y) == entry)
+        }) {
+            self.cursor_controller.cursor_y = y;
+        }
+    }
+
+    /** 目录列表中的按键，返回 false 时交给普通的按键处理，会修改内容的按键都在这里被拦下 */
+    fn browser_keypress(&mut self, key: KeyEvent) -> io::Result<bool> {
+        let dir = match &self.editor_rows.directory {
+            Some(dir) => dir.clone(),
+            None => return Ok(false),
+        };
+        let y = self.cursor_controller.cursor_y;
+        let entry = if y < self.editor_rows.number_of_rows() {
+            self.editor_rows.get_row(y).to_string()
+        } else {
+            String::new()
+        };
+        match key {
+            KeyEvent {
+                code: KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown,
+                modifiers,
+                ..
+            } if !modifiers.contains(KeyModifiers::ALT) => return Ok(false),
+            KeyEvent {
+                code: KeyCode::Left | KeyCode::Right | KeyCode::Home | KeyCode::End | KeyCode::Esc,
+                ..
+            } => return Ok(false),
+            KeyEvent {
+                code: KeyCode::Char('q' | 'f' | 'g' | 'o' | 'p' | 'e'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Char('g'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => return Ok(false),
+            KeyEvent {
+                code: KeyCode::Enter,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } if entry == browser::PARENT => {
+                let child = dir
+                    .file_name()
+                    .map(|name| format!("{}/", name.to_string_lossy()));
+                self.browse(browser::entry_path(&dir, &entry), child.as_deref())
+            }
+            KeyEvent {
+                code: KeyCode::Enter,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } if !entry.is_empty() => {
+                self.open_path(browser::entry_path(&dir, &entry));
+            }
+            KeyEvent {
+                code: KeyCode::Backspace | KeyCode::Char('-'),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => {
+                let child = dir
+                    .file_name()
+                    .map(|name| format!("{}/", name.to_string_lossy()));
+                self.browse(browser::entry_path(&dir, browser::PARENT), child.as_deref())
+            }
+            KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => {
+                if let Some(name) = prompt!(
+                    self,
+                    "New file (end with / for a directory): {} (ESC to cancel)"
+                ) {
+                    let path = dir.join(&name);
+                    let created = if name.ends_with('/') {
+                        fs::create_dir(&path)
+                    } else {
+                        fs::OpenOptions::new()
+                            .write(true)
+                            .create_new(true)
+                            .open(&path)
+                            .map(|_| ())
+                    };
+                    match created {
+                        Ok(()) => self.browse(dir, Some(&name)),
+                        Err(err) => self.status_message.set_message(err.to_string()),
+                    }
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } if !entry.is_empty() && entry != browser::PARENT => {
+                if let Some(name) = prompt!(self, "Rename {entry} to: {} (ESC to cancel)") {
+                    let path = dir.join(&name);
+                    match fs::rename(browser::entry_path(&dir, &entry), &path) {
+                        Ok(()) => {
+                            let name = name.trim_end_matches('/').to_string()
+                                + if path.is_dir() { "/" } else { "" };
+                            self.browse(dir, Some(&name))
+                        }
+                        Err(err) => self.status_message.set_message(err.to_string()),
+                    }
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char('d'),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } if !entry.is_empty() && entry != browser::PARENT => {
+                let answer = prompt!(self, "Delete {entry}? (y/N): {}");
+                if answer.is_some_and(|it| it.eq_ignore_ascii_case("y")) {
+                    let path = browser::entry_path(&dir, &entry);
+                    // 只删除空目录，避免误删整棵目录树
+                    let deleted = if entry.ends_with('/') {
+                        fs::remove_dir(path)
+                    } else {
+                        fs::remove_file(path)
+                    };
+                    match deleted {
+                        Ok(()) => {
+                            self.browse(dir, None);
+                            let last = self.editor_rows.number_of_rows().saturating_sub(1);
+                            self.cursor_controller.cursor_y = cmp::min(y, last);
+                            self.status_message
+                                .set_message(format!("Deleted {}", entry));
+                        }
+                        Err(err) => self.status_message.set_message(err.to_string()),
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    /** 模糊查找项目中的文件并打开选中的一个 */
+    fn find_file(&mut self) -> io::Result<()> {
+        let root = project::root();
+        let files = project::files(&root);
+        let update = |output: &mut Output, input: &str| {
+            let items: Vec<String> = fuzzy::rank(input, &files, FUZZY_RESULTS)
+                .into_iter()
+                .map(String::from)
+                .collect();
+            let title = format!(
+                "{} of {} files in {}",
+                items.len(),
+                files.len(),
+                root.display()
+            );
+            output.popup = Some(Popup::new(title, items));
+        };
+        update(self, "");
+        let input = prompt!(
+            self,
+            "Find file: {} (Up/Down to select, ESC to cancel)",
+            callback = |output: &mut Output, input: &str, key| match key {
+                KeyCode::Char(_) | KeyCode::Backspace => update(output, input),
+                key => {
+                    let page = output.win_size.1 / 2;
+                    if let Some(popup) = &mut output.popup {
+                        popup.move_selection(key, page);
+                    }
+                }
+            },
+            complete = |_: &str| None,
+            allow_empty = true
+        );
+        let selected = self
+            .popup
+            .take()
+            .and_then(|popup| popup.selected_item().map(|it| root.join(it)));
+        if let (Some(_), Some(path)) = (input, selected) {
+            self.open_path(path);
+        }
+        Ok(())
+    }
+
+    /** 打开 path 并把光标移到 (x, y)，path 就是当前文件时只移动光标 */
+    fn open_at(&mut self, path: PathBuf, position: (usize, usize)) {
+        let current = self
+            .editor_rows
+            .filename
+            .as_ref()
+            .and_then(|it| fs::canonicalize(it).ok());
+        if current.is_some() && current == fs::canonicalize(&path).ok() {
+            self.record_jump();
+        } else if !self.open_path(path) {
+            return;
+        }
+        self.go_to(position);
+    }
+
+    /** 在后台开始搜索整个项目，并显示结果列表 */
+    fn search_project(&mut self, pattern: String) -> io::Result<()> {
+        let whole_word = self.config.whole_word;
+        self.project_search = Some(ProjectSearch::start(project::root(), pattern, whole_word));
+        self.show_search_results()
+    }
+
+    /** 把后台搜索新找到的结果加入列表，返回是否有变化 */
+    fn receive_search_results(&mut self) -> bool {
+        let (search, popup) = match (&mut self.project_search, &mut self.popup) {
+            (Some(search), Some(popup)) => (search, popup),
+            _ => return false,
+        };
+        let changed = search.receive();
+        // 展开制表符并把控制字符换成空格，弹出列表按字符数截断时才与显示的宽度一致
+        popup
+            .items
+            .extend(search.hits[popup.items.len()..].iter().map(|hit| {
+                let mut row = Row::new(hit.to_string(), String::new());
+                EditorRows::render_row(&mut row);
+                row.render.replace(char::is_control, " ")
+            }));
+        popup.title = format!(
+            "{} matches for \"{}\"{}",
+            search.hits.len(),
+            search.pattern,
+            if search.is_running() {
+                " (searching...)"
+            } else {
+                ""
+            }
+        );
+        changed
+    }
+
+    /** 显示项目搜索的结果，搜索还在进行时列表会不断更新，Enter 打开选中的一处 */
+    fn show_search_results(&mut self) -> io::Result<()> {
+        let selected = match &self.project_search {
+            Some(search) => search.selected,
+            None => {
+                self.status_message
+                    .set_message("No project search yet".into());
+                return Ok(());
+            }
+        };
+        self.popup = Some(Popup::new(String::new(), Vec::new()));
+        self.receive_search_results();
+        if let Some(popup) = &mut self.popup {
+            popup.selected = cmp::min(selected, popup.items.len().saturating_sub(1));
+        }
+        self.status_message
+            .set_message("Up/Down to select, Enter to open, ESC to close".into());
+        let mut changed = true;
+        let chosen = loop {
+            changed |= self.receive_search_results();
+            if changed {
+                self.refresh_screen()?;
+                changed = false;
+            }
+            let key = match Reader.poll_event(SEARCH_POLL)? {
+                Some(Event::Key(key)) => key,
+                Some(Event::Resize(columns, rows)) => {
+                    self.resize(columns, rows);
+                    changed = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            changed = true;
+            let page = self.win_size.1 / 2;
+            let popup = self.popup.as_mut().unwrap();
+            match key.code {
+                KeyCode::Enter => break popup.selected_item().map(|_| popup.selected),
+                KeyCode::Esc => break None,
+                code => {
+                    popup.move_selection(code, page);
+                }
+            }
+        };
+        self.status_message.set_message(String::new());
+        let popup = self.popup.take().unwrap();
+        let search = self.project_search.as_mut().unwrap();
+        search.selected = popup.selected;
+        if let Some(hit) = chosen.map(|i| &search.hits[i]) {
+            let path = search.root.join(&hit.path);
+            let position = (hit.column, hit.line);
+            self.open_at(path, position);
+        }
+        Ok(())
+    }
+
+    /**
+     * 依次询问要替换的内容（pattern 为 None 时）和替换成的内容，再在整个项目中替换。
+     * split 表示命令中的替换内容含有空格被拆开了，需要在提示中重新输入
+     */
+    fn prompt_replace_in_project(&mut self, pattern: Option<&str>, split: bool) -> io::Result<()> {
+        let pattern = match pattern {
+            Some(pattern) => pattern.to_string(),
+            None => match prompt!(self, "Replace in project: {} (ESC to cancel)") {
+                Some(pattern) => pattern,
+                None => return Ok(()),
+            },
+        };
+        let hint = if split {
+            " (spaces are allowed here)"
+        } else {
+            ""
+        };
+        // 替换成空字符串即删除，需要在这里直接按 Enter 确认
+        if let Some(replacement) = prompt!(
+            self,
+            "Replace {pattern} with{hint}: {} (ESC to cancel)",
+            callback = |_, _, _| {},
+            complete = |_: &str| None,
+            allow_empty = true
+        ) {
+            self.replace_in_project(&pattern, &replacement)?
+        }
+        Ok(())
+    }
+
+    /** 在整个项目中把 pattern 替换为 replacement，先预览所有要修改的行，确认后才写入 */
+    fn replace_in_project(&mut self, pattern: &str, replacement: &str) -> io::Result<()> {
+        let root = project::root();
+        let whole_word = self.config.whole_word;
+        // 正在编辑的文件使用内存中的内容，替换后由用户保存
+        let buffer = self
+            .editor_rows
+            .filename
+            .as_ref()
+            .and_then(|it| fs::canonicalize(it).ok())
+            .filter(|it| it.starts_with(&root));
+        let mut files = replace::collect(&root, pattern, whole_word, buffer.as_deref());
+        let editable = self.editor_rows.hex_view.is_none() && self.editor_rows.large_file.is_none();
+        let buffer_changes = match &buffer {
+            Some(path) if editable => {
+                let rows =
+                    (0..self.editor_rows.number_of_rows()).map(|y| self.editor_rows.get_row(y));
+                let lines = replace::find_lines(rows, pattern, whole_word);
+                let found = !lines.is_empty();
+                if found {
+                    let path = project::relative(&root, path);
+                    files.insert(0, FileChanges { path, lines });
+                }
+                found
+            }
+            _ => false,
+        };
+        if files.is_empty() {
+            self.status_message
+                .set_message(format!("No matches for {}", pattern));
+            return Ok(());
+        }
+        self.popup = Some(Popup::new(String::new(), Vec::new()));
+        self.status_message
+            .set_message("Space to include or exclude, Enter to replace, ESC to cancel".into());
+        let accepted = loop {
+            let (items, targets) = replace::preview(&files, replacement);
+            let lines = files.iter().flat_map(|it| &it.lines);
+            let occurrences = lines
+                .clone()
+                .map(|it| it.included_matches().count())
+                .sum::<usize>();
+            let popup = self.popup.as_mut().unwrap();
+            popup.title = format!(
+                "Replace \"{}\" with \"{}\": {} of {} occurrences selected",
+                pattern,
+                replacement,
+                occurrences,
+                lines.map(|it| it.matches.len()).sum::<usize>()
+            );
+            popup.items = items;
+            self.refresh_screen()?;
+            let key = match Reader.read_event()? {
+                Event::Key(key) => key,
+                Event::Resize(columns, rows) => {
+                    self.resize(columns, rows);
+                    continue;
+                }
+                _ => continue,
+            };
+            let page = self.win_size.1 / 2;
+            let popup = self.popup.as_mut().unwrap();
+            match key.code {
+                KeyCode::Enter => break true,
+                KeyCode::Esc => break false,
+                KeyCode::Char(' ') => replace::toggle(&mut files, targets[popup.selected]),
+                code => {
+                    popup.move_selection(code, page);
+                }
+            }
+        };
+        self.popup = None;
+        if !accepted {
+            self.status_message.set_message("Replace cancelled".into());
+            return Ok(());
+        }
+        let disk_files = &files[usize::from(buffer_changes)..];
+        if let Err(err) = replace::apply(&root, disk_files, replacement) {
+            self.status_message
+                .set_message(format!("Replace failed: {}", err));
+            return Ok(());
+        }
+        if buffer_changes {
+            for change in &files[0].lines {
+                for range in change.included_matches().rev() {
+                    self.replace_in_row(change.line, range.clone(), replacement);
+                }
+            }
+            self.dirty += 1;
+        }
+        let included = |file: &FileChanges| file.lines.iter().filter(|it| it.is_included()).count();
+        let occurrences: usize = files
+            .iter()
+            .flat_map(|it| &it.lines)
+            .map(|it| it.included_matches().count())
+            .sum();
+        self.status_message.set_message(format!(
+            "Replaced {} occurrence(s) on {} line(s) in {} file(s){}",
+            occurrences,
+            files.iter().map(included).sum::<usize>(),
+            files.iter().filter(|it| included(it) > 0).count(),
+            if buffer_changes && included(&files[0]) > 0 {
+                ", save to write the current file"
+            } else {
+                ""
+            }
+        ));
+        Ok(())
+    }
+
+    /** 大文件模式下窗口需要覆盖光标前后的范围，保证翻页后的行已经载入 */
+    fn window_margin(&self) -> usize {
+        3 * self.win_size.1 + 1
+    }
+
+    fn draw_message_bar(&mut self) {
+        queue!(
+            self.editor_contents,
+            terminal::Clear(ClearType::UntilNewLine)
+        )
+        .unwrap();
+        if let Some(msg) = self.status_message.message() {
+            let msg: String = msg.chars().take(self.win_size.0).collect();
+            self.editor_contents.push_str(&msg);
+        }
+    }
+
+    fn delete_char(&mut self) {
+        if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
+            return;
+        }
+        if self.cursor_controller.cursor_y == 0 && self.cursor_controller.cursor_x == 0 {
+            return;
+        }
+        let row = self
+            .editor_rows
+            .get_editor_row_mut(self.cursor_controller.cursor_y);
+        if self.cursor_controller.cursor_x > 0 {
+            let char_len = row.row_content[..self.cursor_controller.cursor_x]
+                .chars()
+                .next_back()
+                .map_or(1, char::len_utf8);
+            self.cursor_controller.cursor_x -= char_len;
+            row.delete_char(self.cursor_controller.cursor_x);
+        } else {
+            let previous_row_content = self
+                .editor_rows
+                .get_row(self.cursor_controller.cursor_y - 1);
+            self.cursor_controller.cursor_x = previous_row_content.len();
+            self.editor_rows
+                .join_adjacent_rows(self.cursor_controller.cursor_y);
+            self.cursor_controller.cursor_y -= 1;
+        }
+        self.dirty += 1;
+    }
+
+    /** 当前缓冲区的缩进方式 */
+    fn indent_style(&self) -> IndentStyle {
+        self.editor_rows.indent.unwrap_or(IndentStyle {
+            use_tabs: !self.config.expand_tabs,
+            width: self.config.indent_width,
+        })
+    }
+
+    /** 把整个缓冲区的行首缩进转换为 tab 或空格 */
+    fn convert_indent(&mut self, use_tabs: bool) -> Result<(), String> {
+        if self.editor_rows.large_file.is_some() || self.editor_rows.hex_view.is_some() {
+            return Err("Conversion is only available in text mode".into());
+        }
+        let style = IndentStyle {
+            use_tabs,
+            ..self.indent_style()
+        };
+        let y = self.cursor_controller.cursor_y;
+        for at in 0..self.editor_rows.number_of_rows() {
+            let converted = style.reindent(self.editor_rows.get_row(at));
+            if converted == self.editor_rows.get_row(at) {
+                continue;
+            }
+            let row = self.editor_rows.get_editor_row_mut(at);
+            if at == y {
+                // 光标保持在原来的非空白字符上
+                let old_indent = leading_whitespace(&row.row_content).len();
+                let new_indent = leading_whitespace(&converted).len();
+                let x = &mut self.cursor_controller.cursor_x;
+                *x = if *x >= old_indent {
+                    *x + new_indent - old_indent
+                } else {
+                    cmp::min(*x, new_indent)
+                };
+            }
+            row.row_content = converted;
+            EditorRows::render_row(row);
+        }
+        self.editor_rows.indent = Some(style);
+        self.dirty += 1;
+        Ok(())
+    }
+
+    /** 退格：光标前全是空格时删除到上一个缩进位置 */
+    fn backspace(&mut self) {
+        let (x, y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        if self.config.auto_pair {
+            let file_type = self.editor_rows.file_type();
+            if let (Some(before), Some(after)) = self.chars_around_cursor() {
+                // 删除空的一对括号或引号
+                if file_type.closing_pair(before) == Some(after) {
+                    self.editor_rows.get_editor_row_mut(y).delete_char(x);
+                    return self.delete_char();
+                }
+            }
+        }
+        let style = self.indent_style();
+        if x == 0 || y >= self.editor_rows.number_of_rows() || style.use_tabs {
+            return self.delete_char();
+        }
+        let row = self.editor_rows.get_row(y);
+        if !row[..x].bytes().all(|b| b == b' ') {
+            return self.delete_char();
+        }
+        let new_x = x - ((x - 1) % style.width + 1);
+        let row = self.editor_rows.get_editor_row_mut(y);
+        row.row_content.replace_range(new_x..x, "");
+        EditorRows::render_row(row);
+        self.cursor_controller.cursor_x = new_x;
+        self.dirty += 1;
+    }
+
+    /** 新行继承当前行的缩进，在 { ( [ 等之后换行时再增加一级 */
+    fn insert_indented_newline(&mut self) {
+        let (x, y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let file_type = self.editor_rows.file_type();
+        let row = self.editor_rows.get_row(y);
+        let indent = leading_whitespace(row).to_string();
+        if x <= indent.len() {
+            // 光标在行首的空白中，直接在上方插入空行
+            self.editor_rows.insert_row(y, String::new());
+            self.cursor_controller.cursor_y += 1;
+            self.dirty += 1;
+            return;
+        }
+        let opener = row[..x]
+            .trim_end()
+            .chars()
+            .next_back()
+            .filter(|&c| file_type.opens_indent(c));
+        let tail = row[x..].trim_start().to_string();
+        let mut new_indent = indent.clone();
+        if opener.is_some() {
+            new_indent.push_str(&self.indent_style().unit());
+        }
+        let row = self.editor_rows.get_editor_row_mut(y);
+        row.row_content.truncate(x);
+        EditorRows::render_row(row);
+        // 在 {} 之间换行时，右括号单独放到下一行
+        if matches!(opener.and_then(filetype::closing_bracket), Some(c) if tail.starts_with(c)) {
+            self.editor_rows
+                .insert_row(y + 1, format!("{}{}", indent, tail));
+            self.editor_rows.insert_row(y + 1, new_indent.clone());
+        } else {
+            self.editor_rows
+                .insert_row(y + 1, format!("{}{}", new_indent, tail));
+        }
+        self.cursor_controller.cursor_x = new_indent.len();
+        self.cursor_controller.cursor_y += 1;
+        self.dirty += 1;
+    }
+
+    /** 在空白行上输入右括号时减少一级缩进 */
+    fn auto_dedent(&mut self, ch: char) {
+        let (x, y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        if y >= self.editor_rows.number_of_rows() || !self.editor_rows.file_type().closes_indent(ch)
+        {
+            return;
+        }
+        let width = self.indent_style().width;
+        let row = self.editor_rows.get_row(y);
+        if x == 0 || x != row.len() || !row.trim().is_empty() {
+            return;
+        }
+        let new_len = if row.ends_with('\t') {
+            x - 1
+        } else {
+            let trailing_spaces = row.len() - row.trim_end_matches(' ').len();
+            x - cmp::min(trailing_spaces, (x - 1) % width + 1)
+        };
+        let row = self.editor_rows.get_editor_row_mut(y);
+        row.row_content.truncate(new_len);
+        EditorRows::render_row(row);
+        self.cursor_controller.cursor_x = new_len;
+    }
+
+    /** rows 是否都已载入，大文件模式下超出窗口时给出提示 */
+    fn rows_loaded(&mut self, rows: Range<usize>) -> bool {
+        let loaded = rows.is_empty()
+            || (self.editor_rows.is_loaded(rows.start) && self.editor_rows.is_loaded(rows.end - 1));
+        if !loaded {
+            self.status_message
+                .set_message("Selection is too large to edit in large file mode".into());
+        }
+        loaded
+    }
+
+    /** 有选区时为选中的各行，否则为光标所在行 */
+    fn selected_rows(&self) -> Range<usize> {
+        let y = self.cursor_controller.cursor_y;
+        let rows = match self.cursor_controller.selection() {
+            // 选区结束于行首时不包括该行
+            Some(((_, start_y), (0, end_y))) if end_y > start_y => start_y..end_y,
+            Some(((_, start_y), (_, end_y))) => start_y..end_y + 1,
+            None => y..y + 1,
+        };
+        rows.start..cmp::min(rows.end, self.editor_rows.number_of_rows())
+    }
+
+    /** 把第 y 行的 range 替换为 text，光标和选区起点随之移动 */
+    fn replace_in_row(&mut self, y: usize, range: Range<usize>, text: &str) {
+        let row = self.editor_rows.get_editor_row_mut(y);
+        row.row_content.replace_range(range.clone(), text);
+        EditorRows::render_row(row);
+        let shift = |x: &mut usize| {
+            if *x >= range.end {
+                *x = *x - range.len() + text.len();
+            } else if *x > range.start {
+                *x = range.start;
+            }
+        };
+        if self.cursor_controller.cursor_y == y {
+            shift(&mut self.cursor_controller.cursor_x);
+        }
+        if let Some((x, anchor_y)) = &mut self.cursor_controller.selection_anchor {
+            if *anchor_y == y {
+                shift(x);
+            }
+        }
+    }
+
+    /** 把当前行或选中的各行增加（forward）或减少一级缩进 */
+    fn shift_rows(&mut self, forward: bool) {
+        let rows = self.selected_rows();
+        if rows.is_empty() {
+            return;
+        }
+        if !self.rows_loaded(rows.clone()) {
+            return;
+        }
+        let style = self.indent_style();
+        let unit = style.unit();
+        let mut changed = false;
+        for y in rows {
+            let row = self.editor_rows.get_row(y);
+            if forward {
+                if row.trim().is_empty() {
+                    continue;
+                }
+                self.replace_in_row(y, 0..0, &unit);
+            } else {
+                let len = if row.starts_with('\t') {
+                    1
+                } else {
+                    row.len() - row.trim_start_matches(' ').len()
+                };
+                let len = cmp::min(len, style.width);
+                if len == 0 {
+                    continue;
+                }
+                self.replace_in_row(y, 0..len, "");
+            }
+            changed = true;
+        }
+        if changed {
+            self.dirty += 1;
+        }
+    }
+
+    /** 光标和选区起点一起上下移动 offset 行 */
+    fn offset_rows(&mut self, offset: isize) {
+        let cursor_controller = &mut self.cursor_controller;
+        cursor_controller.cursor_y = cursor_controller.cursor_y.wrapping_add_signed(offset);
+        if let Some((_, y)) = &mut cursor_controller.selection_anchor {
+            *y = y.wrapping_add_signed(offset);
+        }
+    }
+
+    /** 在下方复制当前行或选中的各行，光标移到副本上 */
+    fn duplicate_rows(&mut self) {
+        let rows = self.selected_rows();
+        if rows.is_empty() || !self.rows_loaded(rows.clone()) {
+            return;
+        }
+        let copies: Vec<String> = rows
+            .clone()
+            .map(|y| self.editor_rows.get_row(y).to_string())
+            .collect();
+        for (i, copy) in copies.into_iter().enumerate() {
+            self.editor_rows.insert_row(rows.end + i, copy);
+        }
+        self.offset_rows(rows.len() as isize);
+        self.dirty += 1;
+    }
+
+    /** 把当前行或选中的各行与上方（up）或下方的一行交换位置 */
+    fn move_rows(&mut self, up: bool) {
+        let rows = self.selected_rows();
+        if rows.is_empty() {
+            return;
+        }
+        let span = if up {
+            match rows.start.checked_sub(1) {
+                Some(start) => start..rows.end,
+                None => return,
+            }
+        } else {
+            rows.start..rows.end + 1
+        };
+        if span.end > self.editor_rows.number_of_rows() || !self.rows_loaded(span.clone()) {
+            return;
+        }
+        self.editor_rows.rotate_rows(span, up);
+        self.offset_rows(if up { -1 } else { 1 });
+        self.dirty += 1;
+    }
+
+    /** 删除当前行或选中的各行 */
+    fn delete_rows(&mut self) {
+        let rows = self.selected_rows();
+        if rows.is_empty() || !self.rows_loaded(rows.clone()) {
+            return;
+        }
+        self.editor_rows.remove_rows(rows.clone());
+        self.cursor_controller.selection_anchor = None;
+        self.cursor_controller.cursor_y = rows.start;
+        self.cursor_controller.cursor_x = if rows.start < self.editor_rows.number_of_rows() {
+            leading_whitespace(self.editor_rows.get_row(rows.start)).len()
+        } else {
+            0
+        };
+        self.dirty += 1;
+    }
+
+    /** 合并选中的各行，没有选区时把下一行合并到当前行，行间只保留一个空格 */
+    fn join_rows(&mut self) {
+        let mut rows = self.selected_rows();
+        if rows.len() == 1 {
+            rows.end += 1;
+        }
+        if rows.len() < 2
+            || rows.end > self.editor_rows.number_of_rows()
+            || !self.rows_loaded(rows.clone())
+        {
+            return;
+        }
+        let mut joined = self.editor_rows.get_row(rows.start).trim_end().to_string();
+        let mut x = joined.len();
+        for y in rows.start + 1..rows.end {
+            let next = self.editor_rows.get_row(y).trim();
+            x = joined.len();
+            if !joined.is_empty() && !next.is_empty() {
+                joined.push(' ');
+            }
+            joined.push_str(next);
+        }
+        let row = self.editor_rows.get_editor_row_mut(rows.start);
+        row.row_content = joined;
+        EditorRows::render_row(row);
+        self.editor_rows.remove_rows(rows.start + 1..rows.end);
+        self.cursor_controller.selection_anchor = None;
+        self.cursor_controller.cursor_y = rows.start;
+        self.cursor_controller.cursor_x = x;
+        self.dirty += 1;
+    }
+
+    /** 用 f 处理选中的各行（没有选区时为整个文件）后写回 */
+    fn transform_rows(&mut self, f: impl FnOnce(&mut Vec<String>)) {
+        let rows = match self.cursor_controller.selection() {
+            Some(_) => self.selected_rows(),
+            None => 0..self.editor_rows.number_of_rows(),
+        };
+        if rows.is_empty() || !self.rows_loaded(rows.clone()) {
+            return;
+        }
+        let original: Vec<String> = rows
+            .clone()
+            .map(|y| self.editor_rows.get_row(y).to_string())
+            .collect();
+        let mut lines = original.clone();
+        f(&mut lines);
+        if lines == original {
+            return;
+        }
+        self.editor_rows.replace_rows(rows.clone(), lines);
+        self.cursor_controller.selection_anchor = None;
+        self.cursor_controller.cursor_x = 0;
+        self.cursor_controller.cursor_y = rows.start;
+        self.dirty += 1;
+    }
+
+    /** 转换选区（没有选区时为光标处的单词）的大小写 */
+    fn change_case(&mut self, case: Case) {
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let ((start_x, start_y), (mut end_x, mut end_y)) = match self.cursor_controller.selection()
+        {
+            Some(selection) => selection,
+            None => {
+                let (x, y) = (
+                    self.cursor_controller.cursor_x,
+                    self.cursor_controller.cursor_y,
+                );
+                if y >= number_of_rows {
+                    return;
+                }
+                let word = self.editor_rows.get_editor_row(y).word_at(x);
+                ((word.start, y), (word.end, y))
+            }
+        };
+        if end_y >= number_of_rows {
+            end_y = number_of_rows - 1;
+            end_x = self.editor_rows.get_row(end_y).len();
+        }
+        if !self.rows_loaded(start_y..end_y + 1) {
+            return;
+        }
+        let mut changed = false;
+        for y in start_y..=end_y {
+            let row = self.editor_rows.get_row(y);
+            let start = if y == start_y { start_x } else { 0 };
+            let end = if y == end_y { end_x } else { row.len() };
+            let converted = case.apply(&row[start..end]);
+            if converted != row[start..end] {
+                self.replace_in_row(y, start..end, &converted);
+                changed = true;
+            }
+        }
+        if changed {
+            self.dirty += 1;
+        }
+    }
+
+    /** 在每个光标处执行 edit。从后往前处理，已处理的光标记为到文件末尾的行数和到行尾的字节数，
+    前面的修改不会改变这两个值 */
+    fn edit_at_cursors(&mut self, mut edit: impl FnMut(&mut Self)) {
+        // 大文件模式下其他光标所在的行可能不在窗口中，只编辑主光标处
+        if self.editor_rows.large_file.is_some() {
+            self.cursor_controller.extra_cursors.clear();
+        }
+        if self.cursor_controller.extra_cursors.is_empty() {
+            return edit(self);
+        }
+        let primary = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let mut cursors = std::mem::take(&mut self.cursor_controller.extra_cursors);
+        cursors.push(primary);
+        cursors.sort_by_key(|&(x, y)| cmp::Reverse((y, x)));
+        cursors.dedup();
+        self.cursor_controller.selection_anchor = None;
+        let mut done = Vec::with_capacity(cursors.len());
+        for (x, y) in cursors {
+            let is_primary = (x, y) == primary;
+            self.cursor_controller.cursor_x = x;
+            self.cursor_controller.cursor_y = y;
+            edit(self);
+            let (x, y) = (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            );
+            let row_len = self.row_len(y);
+            let from_end = (self.editor_rows.number_of_rows() - y, row_len - x);
+            done.push((from_end, is_primary));
+        }
+        let number_of_rows = self.editor_rows.number_of_rows();
+        for ((rows_from_end, bytes_from_end), is_primary) in done {
+            let y = number_of_rows.saturating_sub(rows_from_end);
+            let x = self.row_len(y).saturating_sub(bytes_from_end);
+            if is_primary {
+                self.cursor_controller.cursor_x = x;
+                self.cursor_controller.cursor_y = y;
+            } else {
+                self.cursor_controller.extra_cursors.push((x, y));
+            }
+        }
+        self.normalize_cursors();
+    }
+
+    /** 第 y 行的长度，光标在最后一行之后时为 0 */
+    fn row_len(&self, y: usize) -> usize {
+        if y < self.editor_rows.number_of_rows() {
+            self.editor_rows.get_row(y).len()
+        } else {
+            0
+        }
+    }
+
+    /** 把所有光标放到字符边界上，去掉重合的光标 */
+    fn normalize_cursors(&mut self) {
+        let primary = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let mut cursors = std::mem::take(&mut self.cursor_controller.extra_cursors);
+        for (x, y) in &mut cursors {
+            let row = if *y < self.editor_rows.number_of_rows() {
+                self.editor_rows.get_row(*y)
+            } else {
+                ""
+            };
+            *x = cmp::min(*x, row.len());
+            while !row.is_char_boundary(*x) {
+                *x -= 1;
+            }
+        }
+        cursors.sort_by_key(|&(x, y)| (y, x));
+        cursors.dedup();
+        cursors.retain(|&cursor| cursor != primary);
+        self.cursor_controller.extra_cursors = cursors;
+    }
+
+    /** 方向键同时移动所有光标 */
+    fn move_extra_cursors(&mut self, direction: KeyCode) {
+        let cursor_controller = &mut self.cursor_controller;
+        let primary = (cursor_controller.cursor_x, cursor_controller.cursor_y);
+        let mut cursors = std::mem::take(&mut cursor_controller.extra_cursors);
+        for cursor in &mut cursors {
+            (cursor_controller.cursor_x, cursor_controller.cursor_y) = *cursor;
+            cursor_controller.move_cursor(direction, &self.editor_rows);
+            *cursor = (cursor_controller.cursor_x, cursor_controller.cursor_y);
+        }
+        (cursor_controller.cursor_x, cursor_controller.cursor_y) = primary;
+        cursor_controller.extra_cursors = cursors;
+        self.normalize_cursors();
+    }
+
+    fn multiple_cursors_available(&mut self) -> bool {
+        if self.editor_rows.large_file.is_some() {
+            self.status_message
+                .set_message("Multiple cursors are not available for large files".into());
+            return false;
+        }
+        true
+    }
+
+    /** 在最上方（up）或最下方的光标的上一行或下一行增加一个光标，保持显示列不变 */
+    fn add_cursor_vertical(&mut self, up: bool) {
+        if !self.multiple_cursors_available() {
+            return;
+        }
+        let cursor_controller = &self.cursor_controller;
+        let primary = (cursor_controller.cursor_x, cursor_controller.cursor_y);
+        let cursors = cursor_controller.extra_cursors.iter().chain(Some(&primary));
+        let (x, y) = if up {
+            cursors.min_by_key(|&&(x, y)| (y, x))
+        } else {
+            cursors.max_by_key(|&&(x, y)| (y, x))
+        }
+        .copied()
+        .unwrap();
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let target = match (up, y) {
+            (true, 0) => return,
+            (true, y) => y - 1,
+            (false, y) if y + 1 >= number_of_rows => return,
+            (false, y) => y + 1,
+        };
+        if !self.editor_rows.is_loaded(target) {
+            return;
+        }
+        let render_x = match y < number_of_rows {
+            true => self.editor_rows.get_editor_row(y).get_render_x(x),
+            false => 0,
+        };
+        let target_x = self
+            .editor_rows
+            .get_editor_row(target)
+            .get_row_content_x(render_x);
+        self.cursor_controller.selection_anchor = None;
+        self.cursor_controller
+            .extra_cursors
+            .push((target_x, target));
+        self.normalize_cursors();
+        self.show_cursor_count();
+    }
+
+    /** 在光标处单词的下一个出现位置增加一个光标，光标在单词中的相对位置不变 */
+    fn add_cursor_at_next_occurrence(&mut self) {
+        if !self.multiple_cursors_available() {
+            return;
+        }
+        let (x, y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        if y >= self.editor_rows.number_of_rows() {
+            return;
+        }
+        let word_range = self.editor_rows.get_editor_row(y).word_at(x);
+        let word = self.editor_rows.get_row(y)[word_range.clone()].to_string();
+        if !word.chars().all(is_word_char) || word.is_empty() {
+            self.status_message
+                .set_message("No word under the cursor".into());
+            return;
+        }
+        let offset = x - word_range.start;
+        // 从最后一个光标之后开始查找，到达载入范围末尾后从头开始
+        let (last_x, last_y) = self
+            .cursor_controller
+            .extra_cursors
+            .iter()
+            .copied()
+            .chain(Some((x, y)))
+            .max_by_key(|&(x, y)| (y, x))
+            .unwrap();
+        let window_start = self.editor_rows.window_start();
+        let loaded = window_start..window_start + self.editor_rows.row_contents.len();
+        // 从最后一个光标所在单词的第一个字符之后开始，多字节字符不能只跳过一个字节
+        let last_row = self.editor_rows.get_row(last_y);
+        let start_x = (last_x.saturating_sub(offset) + 1..)
+            .find(|&i| i >= last_row.len() || last_row.is_char_boundary(i))
+            .unwrap();
+        let rows = (last_y..loaded.end).chain(loaded.start..=last_y);
+        for (i, row_y) in rows.enumerate() {
+            let row = self.editor_rows.get_row(row_y);
+            let from = match i {
+                0 => cmp::min(start_x, row.len()),
+                _ => 0,
+            };
+            let mut search_from = from;
+            while let Some(index) = row
+                .get(search_from..)
+                .and_then(|rest| find_keyword(rest, &word, true))
+            {
+                let cursor = (search_from + index + offset, row_y);
+                let exists =
+                    cursor == (x, y) || self.cursor_controller.extra_cursors.contains(&cursor);
+                if !exists {
+                    self.cursor_controller.selection_anchor = None;
+                    self.cursor_controller.extra_cursors.push(cursor);
+                    self.normalize_cursors();
+                    self.show_cursor_count();
+                    return;
+                }
+                search_from += index + word.len();
+            }
+        }
+        self.status_message
+            .set_message(format!("No more occurrences of {}", word));
+    }
+
+    fn show_cursor_count(&mut self) {
+        self.status_message.set_message(format!(
+            "{} cursors (Esc to keep only one)",
+            self.cursor_controller.extra_cursors.len() + 1
+        ));
+    }
+
+    /** 列选择的行范围和显示列范围 */
+    fn column_rect(&self) -> Option<(Range<usize>, Range<usize>)> {
+        let cursor_controller = &self.cursor_controller;
+        let anchor = cursor_controller.column_anchor?;
+        let render_x = |(x, y): (usize, usize)| match y < self.editor_rows.number_of_rows() {
+            true => self.editor_rows.get_editor_row(y).get_render_x(x),
+            false => 0,
+        };
+        let (a, b) = (render_x(anchor), cursor_controller.column_x);
+        let rows = cmp::min(anchor.1, cursor_controller.cursor_y)
+            ..cmp::min(
+                cmp::max(anchor.1, cursor_controller.cursor_y) + 1,
+                self.editor_rows.number_of_rows(),
+            );
+        Some((rows, cmp::min(a, b)..cmp::max(a, b)))
+    }
+
+    /** 开始或扩展列选择，光标一角按显示列移动，可以移到行尾之后 */
+    fn extend_column_selection(&mut self, direction: KeyCode) {
+        // 矩形可以超出大文件模式载入的窗口，绘制和复制时会访问没有载入的行
+        if self.editor_rows.large_file.is_some() {
+            self.status_message
+                .set_message("Column selection is not available for large files".into());
+            return;
+        }
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let editor_rows = &self.editor_rows;
+        let render_width = |y: usize| match y < number_of_rows {
+            true => editor_rows.get_render(y).chars().count(),
+            false => 0,
+        };
+        let cursor_controller = &mut self.cursor_controller;
+        cursor_controller.selection_anchor = None;
+        if cursor_controller.column_anchor.is_none() {
+            let (x, y) = (cursor_controller.cursor_x, cursor_controller.cursor_y);
+            cursor_controller.column_anchor = Some((x, y));
+            cursor_controller.column_x = match y < number_of_rows {
+                true => editor_rows.get_editor_row(y).get_render_x(x),
+                false => 0,
+            };
+        }
+        match direction {
+            KeyCode::Left => {
+                cursor_controller.column_x = cursor_controller.column_x.saturating_sub(1)
+            }
+            KeyCode::Right => cursor_controller.column_x += 1,
+            KeyCode::Up => {
+                cursor_controller.cursor_y = cursor_controller.cursor_y.saturating_sub(1)
+            }
+            KeyCode::Down if cursor_controller.cursor_y + 1 < number_of_rows => {
+                cursor_controller.cursor_y += 1
+            }
+            KeyCode::Home => cursor_controller.column_x = 0,
+            KeyCode::End => cursor_controller.column_x = render_width(cursor_controller.cursor_y),
+            _ => {}
+        }
+        let y = cursor_controller.cursor_y;
+        cursor_controller.cursor_x = match y < number_of_rows {
+            true => editor_rows
+                .get_editor_row(y)
+                .get_row_content_x(cursor_controller.column_x),
+            false => 0,
+        };
+    }
+
+    /** 第 y 行中显示列 columns 对应的字节范围 */
+    fn column_span(&self, y: usize, columns: &Range<usize>) -> Range<usize> {
+        let row = self.editor_rows.get_editor_row(y);
+        row.get_row_content_x(columns.start)..row.get_row_content_x(columns.end)
+    }
+
+    /** 行比 render_x 短时用空格补齐，返回 render_x 对应的字节位置 */
+    fn pad_row_to(&mut self, y: usize, render_x: usize) -> usize {
+        if y == self.editor_rows.number_of_rows() {
+            self.editor_rows.insert_row(y, String::new());
+        }
+        let width = self.editor_rows.get_render(y).chars().count();
+        if width < render_x {
+            let row = self.editor_rows.get_editor_row_mut(y);
+            row.row_content.push_str(&" ".repeat(render_x - width));
+            EditorRows::render_row(row);
+        }
+        self.editor_rows
+            .get_editor_row(y)
+            .get_row_content_x(render_x)
+    }
+
+    fn copy_column(&self) -> Option<Vec<String>> {
+        let (rows, columns) = self.column_rect()?;
+        Some(
+            rows.map(|y| self.editor_rows.get_row(y)[self.column_span(y, &columns)].to_string())
+                .collect(),
+        )
+    }
+
+    /** 删除列选择的内容，光标移到矩形的左上角 */
+    fn delete_column(&mut self) {
+        let (rows, columns) = match self.column_rect() {
+            Some(rect) => rect,
+            None => return,
+        };
+        self.cursor_controller.column_anchor = None;
+        if rows.is_empty() || !self.rows_loaded(rows.clone()) {
+            return;
+        }
+        for y in rows.clone() {
+            let span = self.column_span(y, &columns);
+            self.replace_in_row(y, span, "");
+        }
+        self.cursor_controller.cursor_y = rows.start;
+        self.cursor_controller.cursor_x = self.column_span(rows.start, &columns).start;
+        self.dirty += 1;
+    }
+
+    /** 用 ch 填满列选择的矩形，较短的行先用空格补齐 */
+    fn fill_column(&mut self, ch: char) {
+        let (rows, columns) = match self.column_rect() {
+            Some(rect) => rect,
+            None => {
+                self.status_message
+                    .set_message("Fill needs a column selection (Alt-Shift-arrows)".into());
+                return;
+            }
+        };
+        if rows.is_empty() || columns.is_empty() || !self.rows_loaded(rows.clone()) {
+            return;
+        }
+        let fill = ch.to_string().repeat(columns.len());
+        for y in rows {
+            self.pad_row_to(y, columns.end);
+            let span = self.column_span(y, &columns);
+            self.replace_in_row(y, span, &fill);
+        }
+        self.dirty += 1;
+    }
+
+    /** 删除列选择的内容，并在矩形左边界的每一行放一个光标，之后的输入作用于每一行 */
+    fn column_to_cursors(&mut self) {
+        let (rows, columns) = match self.column_rect() {
+            Some(rect) => rect,
+            None => return,
+        };
+        let cursor_y = self.cursor_controller.cursor_y;
+        self.delete_column();
+        if rows.is_empty() || !self.rows_loaded(rows.clone()) || !self.multiple_cursors_available()
+        {
+            return;
+        }
+        for y in rows {
+            let x = self.pad_row_to(y, columns.start);
+            if y == cursor_y {
+                self.cursor_controller.cursor_x = x;
+                self.cursor_controller.cursor_y = y;
+            } else {
+                self.cursor_controller.extra_cursors.push((x, y));
+            }
+        }
+        self.normalize_cursors();
+    }
+
+    /** 从光标所在的显示列开始，把各行依次插入到下面的各行中 */
+    fn paste_block(&mut self, lines: &[String]) {
+        let y = self.cursor_controller.cursor_y;
+        if !self.rows_loaded(y..cmp::min(y + lines.len(), self.editor_rows.number_of_rows())) {
+            return;
+        }
+        let render_x = match y < self.editor_rows.number_of_rows() {
+            true => self
+                .editor_rows
+                .get_editor_row(y)
+                .get_render_x(self.cursor_controller.cursor_x),
+            false => 0,
+        };
+        for (i, line) in lines.iter().enumerate() {
+            let x = self.pad_row_to(y + i, render_x);
+            let row = self.editor_rows.get_editor_row_mut(y + i);
+            row.row_content.insert_str(x, line);
+            EditorRows::render_row(row);
+        }
+        self.dirty += 1;
+    }
+
+    /** 选中的文本 */
+    fn selection_text(&self) -> Option<String> {
+        let ((start_x, start_y), (end_x, end_y)) = self.cursor_controller.selection()?;
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let (end_x, end_y) = if end_y >= number_of_rows {
+            let end_y = number_of_rows.checked_sub(1)?;
+            (self.editor_rows.get_row(end_y).len(), end_y)
+        } else {
+            (end_x, end_y)
+        };
+        if !self.editor_rows.is_loaded(start_y) || !self.editor_rows.is_loaded(end_y) {
+            return None;
+        }
+        Some(if start_y == end_y {
+            self.editor_rows.get_row(start_y)[start_x..end_x].to_string()
+        } else {
+            let mut text = self.editor_rows.get_row(start_y)[start_x..].to_string();
+            for y in start_y + 1..end_y {
+                text.push('\n');
+                text.push_str(self.editor_rows.get_row(y));
+            }
+            text.push('\n');
+            text.push_str(&self.editor_rows.get_row(end_y)[..end_x]);
+            text
+        })
+    }
+
+    /** 复制选区或列选择，cut 时同时删除 */
+    fn copy(&mut self, cut: bool) {
+        let clipboard = match self.copy_column() {
+            Some(lines) => Clipboard::Block(lines),
+            None => match self.selection_text() {
+                Some(text) => Clipboard::Text(text),
+                None => return,
+            },
+        };
+        self.clipboard = Some(clipboard);
+        if cut {
+            self.delete_selection();
+        }
+    }
+
+    fn paste_clipboard(&mut self) {
+        match self.clipboard.take() {
+            Some(Clipboard::Text(text)) => {
+                self.column_to_cursors();
+                self.edit_at_cursors(|output| output.paste(&text));
+                self.clipboard = Some(Clipboard::Text(text));
+            }
+            Some(Clipboard::Block(lines)) => {
+                self.delete_selection();
+                self.paste_block(&lines);
+                self.clipboard = Some(Clipboard::Block(lines));
+            }
+            None => {}
+        }
+    }
+
+    /** 载入范围内可以折叠的区间 */
+    fn fold_regions(&self) -> Vec<Range<usize>> {
+        let start = self.editor_rows.window_start();
+        fold::regions(
+            self.editor_rows.file_type(),
+            start..start + self.editor_rows.row_contents.len(),
+            |y| self.editor_rows.get_row(y),
+        )
+    }
+
+    /** 光标移到所在折叠的标题行，避免 scroll 把折叠重新展开 */
+    fn move_out_of_folds(&mut self) {
+        let cursor_controller = &mut self.cursor_controller;
+        let folds = &self.editor_rows.folds;
+        if folds.is_hidden(cursor_controller.cursor_y) {
+            cursor_controller.cursor_y = folds
+                .previous_visible(cursor_controller.cursor_y + 1)
+                .unwrap();
+            cursor_controller.cursor_x = 0;
+        }
+    }
+
+    /** 折叠以光标所在行开始的区间，没有时折叠包含光标的最内层区间 */
+    fn fold_at_cursor(&mut self) {
+        let y = self.cursor_controller.cursor_y;
+        let regions = self.fold_regions();
+        let region = regions.iter().find(|it| it.start == y + 1).or_else(|| {
+            regions
+                .iter()
+                .filter(|it| it.contains(&y))
+                .max_by_key(|it| it.start)
+        });
+        match region {
+            Some(region) => {
+                self.editor_rows.folds.fold(region.clone());
+                self.move_out_of_folds();
+            }
+            None => self
+                .status_message
+                .set_message("Nothing to fold here".into()),
+        }
+    }
+
+    fn unfold_at_cursor(&mut self) {
+        if !self
+            .editor_rows
+            .folds
+            .unfold(self.cursor_controller.cursor_y)
+        {
+            self.status_message
+                .set_message("No fold at the cursor".into());
+        }
+    }
+
+    /** 折叠嵌套深度不小于 level 的所有区间，level 为 0 时折叠所有区间 */
+    fn fold_all(&mut self, level: usize) {
+        let regions = self.fold_regions();
+        for (region, depth) in regions.iter().zip(fold::depths(&regions)) {
+            if depth >= level {
+                self.editor_rows.folds.fold(region.clone());
+            }
+        }
+        self.move_out_of_folds();
+    }
+
+    /** 大范围移动光标之前调用，把当前位置记入跳转历史 */
+    fn record_jump(&mut self) {
+        let position = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        self.editor_rows.marks.record_jump(position);
+    }
+
+    /** 把光标移到 (x, y)，超出文件范围时移到最接近的位置 */
+    fn go_to(&mut self, (x, y): (usize, usize)) {
+        self.editor_rows.index_to(y + 1);
+        let y = cmp::min(y, self.editor_rows.number_of_rows());
+        let margin = self.window_margin();
+        self.editor_rows.load_window(y, margin);
+        let x = if y < self.editor_rows.number_of_rows() {
+            let row = self.editor_rows.get_row(y);
+            let mut x = cmp::min(x, row.len());
+            while !row.is_char_boundary(x) {
+                x -= 1;
+            }
+            x
+        } else {
+            0
+        };
+        let cursor_controller = &mut self.cursor_controller;
+        cursor_controller.update_selection(false);
+        cursor_controller.cursor_x = x;
+        cursor_controller.cursor_y = y;
+    }
+
+    fn goto_line(&mut self, line: &str) {
+        match line.trim().parse::<usize>() {
+            Ok(line) if line > 0 => {
+                self.record_jump();
+                self.go_to((0, line - 1));
+            }
+            _ => self
+                .status_message
+                .set_message(format!("Invalid line number: {}", line)),
+        }
+    }
+
+    fn jump_back(&mut self, forward: bool) {
+        let position = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let marks = &mut self.editor_rows.marks;
+        let target = if forward {
+            marks.forward()
+        } else {
+            marks.back(position)
+        };
+        match target {
+            Some(target) => self.go_to(target),
+            None => self.status_message.set_message(
+                if forward {
+                    "Already at the newest position"
+                } else {
+                    "Already at the oldest position"
+                }
+                .into(),
+            ),
+        }
+    }
+
+    /** 把书签写入书签文件，没有文件名时什么也不做 */
+    fn save_bookmarks(&mut self) {
+        if let Some(filename) = &self.editor_rows.filename {
+            if let Err(err) = self.editor_rows.marks.save(filename) {
+                self.status_message
+                    .set_message(format!("Could not save bookmarks: {}", err));
+            }
+        }
+    }
+
+    /** 在光标处设置书签，name 为 None 时使用最小的未使用编号 */
+    fn set_bookmark(&mut self, name: Option<&str>) {
+        let position = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let marks = &mut self.editor_rows.marks;
+        let name = name.map_or_else(|| marks.next_number(), String::from);
+        marks.set(&name, position);
+        // 书签在保存文件时才写入，未保存的修改会使行号与磁盘上的文件不一致
+        self.status_message.set_message(format!(
+            "Bookmark {} set at line {}, save the file to keep it",
+            name,
+            position.1 + 1
+        ));
+    }
+
+    /** 删除名为 name 的书签，name 为 None 时删除光标所在行的书签 */
+    fn remove_bookmark(&mut self, name: Option<&str>) {
+        let marks = &mut self.editor_rows.marks;
+        let removed = match name {
+            Some(name) => usize::from(marks.remove(name)),
+            None => marks.remove_on_row(self.cursor_controller.cursor_y),
+        };
+        self.status_message.set_message(if removed > 0 {
+            format!("Removed {} bookmark(s)", removed)
+        } else {
+            "No such bookmark".into()
+        });
+    }
+
+    /** 光标所在行有书签时删除，否则设置一个编号书签 */
+    fn toggle_bookmark(&mut self) {
+        let y = self.cursor_controller.cursor_y;
+        if self
+            .editor_rows
+            .marks
+            .bookmarks()
+            .any(|(_, (_, row))| row == y)
+        {
+            self.remove_bookmark(None);
+        } else {
+            self.set_bookmark(None);
+        }
+    }
+
+    fn jump_to_bookmark(&mut self, name: &str) {
+        match self.editor_rows.marks.get(name) {
+            Some(position) => {
+                self.record_jump();
+                self.go_to(position);
+            }
+            None => self
+                .status_message
+                .set_message(format!("No bookmark named {}", name)),
+        }
+    }
+
+    fn list_bookmarks(&mut self) {
+        let list: Vec<String> = self
+            .editor_rows
+            .marks
+            .bookmarks()
+            .map(|(name, (_, y))| format!("{}:{}", name, y + 1))
+            .collect();
+        self.status_message.set_message(if list.is_empty() {
+            "No bookmarks".into()
+        } else {
+            format!("Bookmarks: {}", list.join(" "))
+        });
+    }
+
+    /** 切换行注释：所有非空行都已注释时取消注释，否则在最小缩进处加上注释 */
+    fn toggle_line_comment(&mut self) {
+        let file_type = self.editor_rows.file_type();
+        let prefix = file_type.line_comment;
+        if prefix.is_empty() {
+            self.status_message
+                .set_message(format!("No line comment syntax for {}", file_type.name));
+            return;
+        }
+        let rows = self.selected_rows();
+        if rows.is_empty() {
+            return;
+        }
+        if !self.rows_loaded(rows.clone()) {
+            return;
+        }
+        let lines: Vec<usize> = rows
+            .filter(|&y| !self.editor_rows.get_row(y).trim().is_empty())
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+        let commented = lines
+            .iter()
+            .all(|&y| self.editor_rows.get_row(y).trim_start().starts_with(prefix));
+        let column = lines
+            .iter()
+            .map(|&y| leading_whitespace(self.editor_rows.get_row(y)).len())
+            .min()
+            .unwrap();
+        for y in lines {
+            if commented {
+                let row = self.editor_rows.get_row(y);
+                let start = leading_whitespace(row).len();
+                let mut end = start + prefix.len();
+                if row[end..].starts_with(' ') {
+                    end += 1;
+                }
+                self.replace_in_row(y, start..end, "");
+            } else {
+                self.replace_in_row(y, column..column, &format!("{} ", prefix));
+            }
+        }
+        self.dirty += 1;
+    }
+
+    /** 用块注释包围选区（没有选区时为当前行的内容），已经被包围时去掉注释 */
+    fn toggle_block_comment(&mut self) {
+        let file_type = self.editor_rows.file_type();
+        let (open, close) = match file_type.block_comment {
+            Some(markers) => markers,
+            None => {
+                self.status_message
+                    .set_message(format!("No block comment syntax for {}", file_type.name));
+                return;
+            }
+        };
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let ((start_x, start_y), (mut end_x, mut end_y)) = match self.cursor_controller.selection()
+        {
+            Some(selection) => selection,
+            None => {
+                let y = self.cursor_controller.cursor_y;
+                if y >= number_of_rows || self.editor_rows.get_row(y).trim().is_empty() {
+                    return;
+                }
+                let row = self.editor_rows.get_row(y);
+                (
+                    (leading_whitespace(row).len(), y),
+                    (row.trim_end().len(), y),
+                )
+            }
+        };
+        // 与 selected_rows 一致，选区结束于行首时在上一行的行尾结束注释
+        if end_y >= number_of_rows || (end_x == 0 && end_y > start_y) {
+            end_y -= 1;
+            end_x = usize::MAX;
+        }
+        if !self.rows_loaded(start_y..end_y + 1) {
+            return;
+        }
+        end_x = cmp::min(end_x, self.editor_rows.get_row(end_y).len());
+        let first = self.editor_rows.get_row(start_y);
+        let open_at = start_x + leading_whitespace(&first[start_x..]).len();
+        let close_end = self.editor_rows.get_row(end_y)[..end_x].trim_end().len();
+        let commented = first[open_at..].starts_with(open)
+            && self.editor_rows.get_row(end_y)[..close_end].ends_with(close)
+            && (start_y, open_at + open.len()) <= (end_y, close_end - close.len());
+        // 先处理结尾，开头的修改不会影响结尾的位置
+        if commented {
+            self.replace_in_row(end_y, close_end - close.len()..close_end, "");
+            self.replace_in_row(start_y, open_at..open_at + open.len(), "");
+        } else {
+            self.replace_in_row(end_y, end_x..end_x, close);
+            self.replace_in_row(start_y, start_x..start_x, open);
+        }
+        self.dirty += 1;
+    }
+
+    /** 光标两侧的字符 */
+    fn chars_around_cursor(&self) -> (Option<char>, Option<char>) {
+        if self.cursor_controller.cursor_y >= self.editor_rows.number_of_rows() {
+            return (None, None);
+        }
+        let row = self.editor_rows.get_row(self.cursor_controller.cursor_y);
+        let (before, after) = row.split_at(self.cursor_controller.cursor_x);
+        (before.chars().next_back(), after.chars().next())
+    }
+
+    /** 输入右半部分时跳过已有的，输入左半部分时补全右半部分，返回是否已处理 */
+    fn auto_pair(&mut self, ch: char) -> bool {
+        let file_type = self.editor_rows.file_type();
+        let (before, after) = self.chars_around_cursor();
+        if after == Some(ch) && file_type.is_closer(ch) {
+            self.cursor_controller.cursor_x += ch.len_utf8();
+            return true;
+        }
+        let closer = match file_type.closing_pair(ch) {
+            Some(closer) => closer,
+            None => return false,
+        };
+        // 只在单词之外补全，避免 don't 这样的输入被打断
+        let free_after = after.is_none_or(|c| c.is_whitespace() || file_type.is_closer(c));
+        let free_before = closer != ch || before.is_none_or(|c| !is_word_char(c) && c != ch);
+        if !free_after || !free_before {
+            return false;
+        }
+        self.insert_char(ch);
+        self.editor_rows
+            .get_editor_row_mut(self.cursor_controller.cursor_y)
+            .insert_char(self.cursor_controller.cursor_x, closer);
+        true
+    }
+
+    /** 处理用户输入的字符，在 insert_char 的基础上加上自动缩进等行为 */
+    fn type_char(&mut self, ch: char) {
+        self.delete_selection();
+        if self.config.auto_indent {
+            self.auto_dedent(ch);
+        }
+        if self.config.auto_pair && self.auto_pair(ch) {
+            return;
+        }
+        let style = self.indent_style();
+        if ch == '\t' && !style.use_tabs {
+            // 插入空格直到下一个缩进位置
+            let render_x = if self.cursor_controller.cursor_y < self.editor_rows.number_of_rows() {
+                self.editor_rows
+                    .get_editor_row(self.cursor_controller.cursor_y)
+                    .get_render_x(self.cursor_controller.cursor_x)
+            } else {
+                0
+            };
+            let spaces = style.width - render_x % style.width;
+            self.paste(&" ".repeat(spaces));
+            return;
+        }
+        self.insert_char(ch);
+    }
+
+    fn insert_newline(&mut self) {
+        self.delete_selection();
+        if self.config.auto_indent
+            && self.cursor_controller.cursor_y < self.editor_rows.number_of_rows()
+        {
+            return self.insert_indented_newline();
+        }
+        if self.cursor_controller.cursor_x == 0 {
+            self.editor_rows
+                .insert_row(self.cursor_controller.cursor_y, String::new())
+        } else {
+            // 当在某行中按下 enter 键，则会新建一行并显示之后的内容
+            let current_row = self
+                .editor_rows
+                .get_editor_row_mut(self.cursor_controller.cursor_y);
+            let new_row_content = current_row.row_content[self.cursor_controller.cursor_x..].into();
+            current_row
+                .row_content
+                .truncate(self.cursor_controller.cursor_x);
+            EditorRows::render_row(current_row);
+            self.editor_rows
+                .insert_row(self.cursor_controller.cursor_y + 1, new_row_content);
+        }
+        self.cursor_controller.cursor_x = 0;
+        self.cursor_controller.cursor_y += 1;
+        self.dirty += 1;
+    }
+
+    fn insert_char(&mut self, ch: char) {
+        self.delete_selection();
+        if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
+            self.editor_rows
+                .insert_row(self.editor_rows.number_of_rows(), String::new());
+            self.dirty += 1;
+        }
+        self.editor_rows
+            .get_editor_row_mut(self.cursor_controller.cursor_y)
+            .insert_char(self.cursor_controller.cursor_x, ch);
+        self.cursor_controller.cursor_x += ch.len_utf8();
+        self.dirty += 1;
+    }
+
+    /** 显示状态栏 */
+    fn draw_status_bar(&mut self) {
+        self.editor_contents
+            .push_str(&style::Attribute::Reverse.to_string());
+        let (size_info, line_info) = match &self.editor_rows.hex_view {
+            Some(hex_view) => (
+                format!("{} bytes", hex_view.bytes.len()),
+                format!("hex | 0x{:x}/0x{:x}", hex_view.cursor, hex_view.bytes.len()),
+            ),
+            None => {
+                // 大文件还没扫描完时行数后面加上 "+"
+                let rows = format!(
+                    "{}{}",
+                    self.editor_rows.number_of_rows(),
+                    if self.editor_rows.is_fully_indexed() {
+                        ""
+                    } else {
+                        "+"
+                    }
+                );
+                (
+                    format!("{} lines", rows),
+                    format!(
+                        "{} | {} | {}/{}",
+                        self.editor_rows.file_type().name,
+                        self.editor_rows.encoding.name(),
+                        self.cursor_controller.cursor_y + 1,
+                        rows
+                    ),
+                )
+            }
+        };
+        let name = match &self.editor_rows.directory {
+            Some(dir) => dir.join("").display().to_string(),
+            None => self
+                .editor_rows
+                .filename
+                .as_ref()
+                .and_then(|path| path.file_name())
+                .and_then(|name| name.to_str())
+                .unwrap_or("[No Name]")
+                .into(),
+        };
+        let info: String = format!(
+            "{} {} -- {}",
+            name,
+            if self.dirty > 0 { "(modified)" } else { "" },
+            size_info
+        )
+        .chars()
+        .take(self.win_size.0)
+        .collect();
+        let info_len = info.chars().count();
+        self.editor_contents.push_str(&info);
+        for i in info_len..self.win_size.0 {
             if self.win_size.0 - i == line_info.len() {
                 self.editor_contents.push_str(&line_info);
                 break;
@@ -549,16 +3350,431 @@ impl Output {
                 self.editor_contents.push(' ')
             }
         }
-        self.editor_contents
-            .push_str(&style::Attribute::Reset.to_string());
-        self.editor_contents.push_str("\r\n");
+        self.editor_contents
+            .push_str(&style::Attribute::Reset.to_string());
+        self.line_break(self.win_size.1);
+    }
+
+    /** 在文本区域底部画出候选列表，覆盖原来的内容 */
+    fn draw_popup(&mut self) -> crossterm::Result<()> {
+        let popup = match &mut self.popup {
+            Some(popup) => popup,
+            None => return Ok(()),
+        };
+        let height = cmp::min(cmp::max(popup.items.len(), 1), self.win_size.1 / 2);
+        if height == 0 {
+            return Ok(());
+        }
+        let columns = self.win_size.0;
+        let top = self.win_size.1 - height - 1;
+        let fit = |line: &str| -> String {
+            let line: String = line.chars().take(columns).collect();
+            let padding = columns - line.chars().count();
+            line + &" ".repeat(padding)
+        };
+        queue!(self.editor_contents, cursor::MoveTo(0, top as u16))?;
+        let reverse = style::Attribute::Reverse.to_string();
+        let reset = style::Attribute::Reset.to_string();
+        self.editor_contents
+            .push_str(&format!("{}{}{}\r\n", reverse, fit(&popup.title), reset));
+        if popup.items.is_empty() {
+            self.editor_contents.push_str(&fit("  (no matches)"));
+        }
+        let selected = popup.selected;
+        for (i, (index, item)) in popup.visible(height).enumerate() {
+            if i > 0 {
+                self.editor_contents.push_str("\r\n");
+            }
+            if index == selected {
+                self.editor_contents.push_str(&format!(
+                    "{}{}{}",
+                    reverse,
+                    fit(&format!("> {}", item)),
+                    reset
+                ));
+            } else {
+                self.editor_contents.push_str(&fit(&format!("  {}", item)));
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_hex_rows(&mut self, hex_view: &HexView) {
+        for i in 0..self.win_size.1 {
+            let row = i + hex_view.row_offset;
+            if row < hex_view.number_of_rows() {
+                let render: String = hex_view
+                    .render_row(row)
+                    .chars()
+                    .take(self.win_size.0)
+                    .collect();
+                self.editor_contents.push_str(&render);
+            } else {
+                self.editor_contents.push('~');
+            }
+            queue!(
+                self.editor_contents,
+                terminal::Clear(ClearType::UntilNewLine)
+            )
+            .unwrap();
+            self.line_break(i);
+        }
+    }
+
+    /** 该行被选中部分的 render 列范围，选区跨过行尾时多选中一列表示换行符 */
+    fn selection_render_range(&self, file_row: usize) -> Option<Range<usize>> {
+        if let Some((rows, columns)) = self.column_rect() {
+            return rows.contains(&file_row).then_some(columns);
+        }
+        let ((start_x, start_y), (end_x, end_y)) = self.cursor_controller.selection()?;
+        if file_row < start_y || file_row > end_y {
+            return None;
+        }
+        let row = self.editor_rows.get_editor_row(file_row);
+        let start = if file_row == start_y {
+            row.get_render_x(start_x)
+        } else {
+            0
+        };
+        let end = if file_row == end_y {
+            row.get_render_x(end_x)
+        } else {
+            row.render.chars().count() + 1
+        };
+        Some(start..end)
+    }
+
+    /** 输出一行可见的部分，highlights 中的列反色显示 */
+    fn draw_render(&mut self, render: &str, highlights: &[Range<usize>]) {
+        let column_offset = self.cursor_controller.column_offset;
+        // render 中可能含有多字节字符，按字符而不是字节截取
+        let mut chars = render.chars().skip(column_offset);
+        let mut reversed = false;
+        for column in
+            column_offset..column_offset + self.win_size.0.saturating_sub(self.gutter_width())
+        {
+            let highlighted = highlights.iter().any(|it| it.contains(&column));
+            let ch = match chars.next() {
+                Some(ch) => ch,
+                None if highlighted => ' ',
+                None => break,
+            };
+            if highlighted != reversed {
+                reversed = highlighted;
+                self.editor_contents.push_str(
+                    &if reversed {
+                        style::Attribute::Reverse
+                    } else {
+                        style::Attribute::Reset
+                    }
+                    .to_string(),
+                );
+            }
+            self.editor_contents.push(ch);
+        }
+        if reversed {
+            self.editor_contents
+                .push_str(&style::Attribute::Reset.to_string());
+        }
+    }
+
+    /** 光标处（或光标前）的括号的匹配位置 */
+    fn matching_bracket(&self) -> Option<(usize, usize)> {
+        let (x, y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        if self.editor_rows.hex_view.is_some() || y >= self.editor_rows.number_of_rows() {
+            return None;
+        }
+        let file_type = self.editor_rows.file_type();
+        let start = self.editor_rows.window_start();
+        let loaded = start..start + self.editor_rows.row_contents.len();
+        let find = |x| {
+            bracket::find_match(
+                file_type,
+                loaded.clone(),
+                |at| self.editor_rows.get_row(at),
+                (x, y),
+            )
+        };
+        let row = self.editor_rows.get_row(y);
+        find(x).or_else(|| {
+            let before = row[..x].chars().next_back()?;
+            find(x - before.len_utf8())
+        })
+    }
+
+    fn jump_to_matching_bracket(&mut self) {
+        match self.matching_bracket() {
+            Some((x, y)) => {
+                self.cursor_controller.selection_anchor = None;
+                self.cursor_controller.cursor_x = x;
+                self.cursor_controller.cursor_y = y;
+            }
+            None => self
+                .status_message
+                .set_message("No matching bracket".into()),
+        }
+    }
+
+    /** 显示 git 修改标记的列宽，文件不在仓库中时为 0 */
+    fn gutter_width(&self) -> usize {
+        match (&self.editor_rows.git, &self.editor_rows.hex_view) {
+            (Some(_), None) => 2,
+            _ => 0,
+        }
+    }
+
+    fn git_gutter_outdated(&self) -> bool {
+        let git = self.editor_rows.git.as_ref();
+        git.is_some_and(|git| git.is_outdated(self.dirty))
+    }
+
+    fn update_git_gutter(&mut self) {
+        if let Some(mut git) = self.editor_rows.git.take() {
+            let number_of_rows = self.editor_rows.number_of_rows();
+            let rows = (0..number_of_rows).map(|y| self.editor_rows.get_row(y));
+            git.update(rows, self.dirty);
+            self.editor_rows.git = Some(git);
+        }
+    }
+
+    fn draw_gutter(&mut self, file_row: usize) {
+        let git = match &self.editor_rows.git {
+            Some(git) => git,
+            None => return,
+        };
+        let marker = match git.change(file_row, self.editor_rows.number_of_rows()) {
+            Some(Change::Added) => '+'.green(),
+            Some(Change::Modified) => '~'.yellow(),
+            Some(Change::Deleted) => '_'.red(),
+            None => style::style(' '),
+        };
+        self.editor_contents.push_str(&format!("{} ", marker));
+    }
+
+    /** 移到下一处（forward）或上一处与 HEAD 不同的地方 */
+    fn jump_to_hunk(&mut self, forward: bool) {
+        self.update_git_gutter();
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let target = match &self.editor_rows.git {
+            Some(git) => git.next_hunk(self.cursor_controller.cursor_y, number_of_rows, forward),
+            None => {
+                self.status_message
+                    .set_message("File is not tracked by git".into());
+                return;
+            }
+        };
+        match target {
+            Some(y) => self.go_to((0, y)),
+            None => self.status_message.set_message(
+                if forward {
+                    "No more changes below"
+                } else {
+                    "No more changes above"
+                }
+                .into(),
+            ),
+        }
+    }
+
+    /** 把光标所在的修改恢复成 HEAD 中的内容 */
+    fn revert_hunk(&mut self) {
+        self.update_git_gutter();
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let (rows, lines) = match self.editor_rows.git.as_ref().and_then(|git| {
+            let hunk = git.hunk_at(self.cursor_controller.cursor_y, number_of_rows)?;
+            Some((hunk.new.clone(), git.base_lines(hunk).to_vec()))
+        }) {
+            Some(hunk) => hunk,
+            None => {
+                self.status_message
+                    .set_message("No change at the cursor".into());
+                return;
+            }
+        };
+        let start = rows.start;
+        self.editor_rows.replace_rows(rows, lines);
+        self.dirty += 1;
+        self.go_to((0, start));
+    }
+
+    /** 比较磁盘上的文件和内存中的内容，side_by_side 时左右并排显示，否则为统一格式 */
+    fn show_diff(&mut self, side_by_side: bool) -> io::Result<()> {
+        let filename = match &self.editor_rows.filename {
+            Some(filename) if self.editor_rows.directory.is_none() => filename.clone(),
+            _ => {
+                self.status_message
+                    .set_message("No file name specified".into());
+                return Ok(());
+            }
+        };
+        if self.editor_rows.hex_view.is_some() || self.editor_rows.large_file.is_some() {
+            self.status_message
+                .set_message("Diff is not available in hex or large file mode".into());
+            return Ok(());
+        }
+        // 文件还不存在时与空文件比较
+        let disk = match fs::read(&filename) {
+            Ok(bytes) => match self.editor_rows.encoding.decode(&bytes) {
+                Some(contents) => contents,
+                None => {
+                    self.status_message.set_message(format!(
+                        "File on disk is not valid {}",
+                        self.editor_rows.encoding.name()
+                    ));
+                    return Ok(());
+                }
+            },
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                self.status_message
+                    .set_message(format!("Cannot read the file: {}", err));
+                return Ok(());
+            }
+        };
+        let old: Vec<&str> = disk.lines().collect();
+        let new: Vec<&str> = (0..self.editor_rows.number_of_rows())
+            .map(|i| self.editor_rows.get_row(i))
+            .collect();
+        let hunks = diff::diff(&old, &new);
+        if hunks.is_empty() {
+            self.status_message
+                .set_message("No changes since the last save".into());
+            return Ok(());
+        }
+        let (rows, starts) = if side_by_side {
+            diff::side_by_side(&old, &new, &hunks)
+        } else {
+            diff::unified(&old, &new, &hunks, DIFF_CONTEXT)
+        };
+        self.status_message.set_message(
+            "n/p = next/previous change, Enter = go to the change, ESC = close".into(),
+        );
+        // current 为当前选中的修改，翻页不会改变它
+        let (mut offset, mut current) = (0, 0);
+        let chosen = loop {
+            let height = self.win_size.1;
+            let max_offset = rows.len().saturating_sub(height);
+            offset = cmp::min(offset, max_offset);
+            let title = format!(
+                "diff {} (disk -> buffer): change {}/{}",
+                filename.display(),
+                current + 1,
+                starts.len()
+            );
+            self.draw_diff(&rows, offset, &title)?;
+            let key = match Reader.read_event()? {
+                Event::Key(key) => key,
+                Event::Resize(columns, rows) => {
+                    self.resize(columns, rows);
+                    continue;
+                }
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => break None,
+                KeyCode::Enter => break Some(starts[current].1),
+                KeyCode::Char(ch @ ('n' | '.' | 'p' | ',')) => {
+                    current = if matches!(ch, 'n' | '.') {
+                        cmp::min(current + 1, starts.len() - 1)
+                    } else {
+                        current.saturating_sub(1)
+                    };
+                    offset = starts[current].0;
+                }
+                KeyCode::Up => offset = offset.saturating_sub(1),
+                KeyCode::Down => offset += 1,
+                KeyCode::PageUp => offset = offset.saturating_sub(height),
+                KeyCode::PageDown => offset += height,
+                KeyCode::Home => offset = 0,
+                KeyCode::End => offset = max_offset,
+                _ => {}
+            }
+        };
+        self.status_message.set_message(String::new());
+        if let Some(line) = chosen {
+            let line = cmp::min(line, self.editor_rows.number_of_rows().saturating_sub(1));
+            self.record_jump();
+            self.go_to((0, line));
+        }
+        Ok(())
+    }
+
+    /** 用差异视图覆盖整个文本区域 */
+    fn draw_diff(&mut self, rows: &[DiffRow], offset: usize, title: &str) -> crossterm::Result<()> {
+        let columns = self.win_size.0;
+        // 展开制表符并截断或补齐到 width 列
+        let fit = |text: &str, width: usize| -> String {
+            let mut row = Row::new(text.into(), String::new());
+            EditorRows::render_row(&mut row);
+            let text: String = row.render.chars().take(width).collect();
+            let padding = width - text.chars().count();
+            text + &" ".repeat(padding)
+        };
+        // 并排显示时左右各占一半，中间用 " | " 分隔，每侧行号占 5 列
+        let half = columns.saturating_sub(3) / 2;
+        let side = |line: &Option<(usize, String)>| -> String {
+            match line {
+                Some((number, text)) => fit(&format!("{:>4} {}", number + 1, text), half),
+                None => " ".repeat(half),
+            }
+        };
+        queue!(self.editor_contents, cursor::Hide, cursor::MoveTo(0, 0))?;
+        for i in 0..self.win_size.1 {
+            let line = match rows.get(offset + i) {
+                Some(DiffRow::Header(text)) => fit(text, columns).cyan().to_string(),
+                Some(DiffRow::Context(text)) => fit(&format!(" {}", text), columns),
+                Some(DiffRow::Removed(text)) => {
+                    fit(&format!("-{}", text), columns).red().to_string()
+                }
+                Some(DiffRow::Added(text)) => {
+                    fit(&format!("+{}", text), columns).green().to_string()
+                }
+                Some(DiffRow::Pair { old, new, changed }) => {
+                    let (old, new) = (side(old), side(new));
+                    if *changed {
+                        format!("{} | {}", old.red(), new.green())
+                    } else {
+                        format!("{} | {}", old, new)
+                    }
+                }
+                None => "~".into(),
+            };
+            self.editor_contents.push_str(&line);
+            queue!(
+                self.editor_contents,
+                terminal::Clear(ClearType::UntilNewLine)
+            )?;
+            self.line_break(i);
+        }
+        if self.bars > 0 {
+            self.editor_contents.push_str(&format!(
+                "{}{}{}",
+                style::Attribute::Reverse,
+                fit(title, columns),
+                style::Attribute::Reset
+            ));
+            self.line_break(self.win_size.1);
+        }
+        if self.bars > 1 {
+            self.draw_message_bar();
+        }
+        self.editor_contents.flush()
     }
 
     fn draw_rows(&mut self) {
+        if let Some(hex_view) = self.editor_rows.hex_view.take() {
+            self.draw_hex_rows(&hex_view);
+            self.editor_rows.hex_view = Some(hex_view);
+            return;
+        }
+        let matching_bracket = self.matching_bracket();
         let screen_rows = self.win_size.1;
         let screen_columns = self.win_size.0;
+        let mut file_row = self.cursor_controller.row_offset;
         for i in 0..screen_rows {
-            let file_row = i + self.cursor_controller.row_offset;
             if file_row >= self.editor_rows.number_of_rows() {
                 if self.editor_rows.number_of_rows() == 0 && i == screen_rows / 3 {
                     let mut welcome = format!("Pound Editor --- Version {}", VERSION);
@@ -576,18 +3792,36 @@ impl Output {
                     self.editor_contents.push('~');
                 }
             } else {
-                let row = self.editor_rows.get_render(file_row);
-                let column_offset = self.cursor_controller.column_offset;
-                let len = cmp::min(row.len().saturating_sub(column_offset), screen_columns);
-                let start = if len == 0 { 0 } else { column_offset };
-                self.editor_contents.push_str(&row[start..start + len])
+                let mut highlights: Vec<Range<usize>> =
+                    self.selection_render_range(file_row).into_iter().collect();
+                for &(x, _) in self
+                    .cursor_controller
+                    .extra_cursors
+                    .iter()
+                    .filter(|&&(_, y)| y == file_row)
+                {
+                    let render_x = self.editor_rows.get_editor_row(file_row).get_render_x(x);
+                    highlights.push(render_x..render_x + 1);
+                }
+                if let Some((x, _)) = matching_bracket.filter(|&(_, y)| y == file_row) {
+                    let render_x = self.editor_rows.get_editor_row(file_row).get_render_x(x);
+                    highlights.push(render_x..render_x + 1);
+                }
+                self.draw_gutter(file_row);
+                let mut render = self.editor_rows.get_render(file_row).clone();
+                if let Some(hidden) = self.editor_rows.folds.hidden_after(file_row) {
+                    let unit = if hidden == 1 { "line" } else { "lines" };
+                    render.push_str(&format!(" [+{} {}]", hidden, unit));
+                }
+                self.draw_render(&render, &highlights);
+                file_row = self.editor_rows.folds.next_visible(file_row);
             }
             queue!(
                 self.editor_contents,
                 terminal::Clear(ClearType::UntilNewLine)
             )
             .unwrap();
-            self.editor_contents.push_str("\r\n");
+            self.line_break(i);
         }
     }
 
@@ -596,14 +3830,70 @@ impl Output {
             .move_cursor(direction, &self.editor_rows);
     }
 
+    fn move_word(&mut self, forward: bool) {
+        self.cursor_controller.move_word(forward, &self.editor_rows);
+    }
+
+    /** 删除到下一个单词末尾或上一个单词开头，有选区时只删除选区 */
+    fn delete_word(&mut self, forward: bool) {
+        if self.delete_selection() {
+            return;
+        }
+        let cursor = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        self.move_word(forward);
+        self.cursor_controller.selection_anchor = Some(cursor);
+        self.delete_selection();
+    }
+
     fn refresh_screen(&mut self) -> crossterm::Result<()> {
-        self.cursor_controller.scroll(&self.editor_rows);
+        let around = if self.cursor_controller.free_scroll {
+            self.cursor_controller.row_offset + self.win_size.1 / 2
+        } else {
+            self.cursor_controller.cursor_y
+        };
+        self.editor_rows.load_window(around, self.window_margin());
+        self.cursor_controller.screen_columns = self.win_size.0.saturating_sub(self.gutter_width());
+        self.cursor_controller.scroll(&mut self.editor_rows);
+        if let Some(hex_view) = &mut self.editor_rows.hex_view {
+            hex_view.scroll(self.win_size.1);
+        }
         queue!(self.editor_contents, cursor::Hide, cursor::MoveTo(0, 0))?;
         self.draw_rows();
-        self.draw_status_bar();
-        self.draw_message_bar();
-        let cursor_x = self.cursor_controller.render_x - self.cursor_controller.column_offset;
-        let cursor_y = self.cursor_controller.cursor_y - self.cursor_controller.row_offset;
+        if self.bars > 0 {
+            self.draw_status_bar();
+        }
+        if self.bars > 1 {
+            self.draw_message_bar();
+        }
+        self.draw_popup()?;
+        let (cursor_x, cursor_y) = match &self.editor_rows.hex_view {
+            Some(hex_view) => (
+                hex_view.cursor_column(),
+                hex_view.cursor / hex::BYTES_PER_ROW - hex_view.row_offset,
+            ),
+            None => {
+                let cursor_controller = &self.cursor_controller;
+                let screen_y = self
+                    .editor_rows
+                    .folds
+                    .visible_between(cursor_controller.row_offset, cursor_controller.cursor_y);
+                // 滚轮滚动后光标可能不在屏幕内，此时不显示光标
+                if cursor_controller.cursor_y < cursor_controller.row_offset
+                    || screen_y >= self.win_size.1
+                    || cursor_controller.render_x < cursor_controller.column_offset
+                {
+                    return self.editor_contents.flush();
+                }
+                (
+                    cursor_controller.render_x - cursor_controller.column_offset
+                        + self.gutter_width(),
+                    screen_y,
+                )
+            }
+        };
         queue!(
             self.editor_contents,
             cursor::MoveTo(cursor_x as u16, cursor_y as u16),
@@ -616,15 +3906,22 @@ impl Output {
 struct Reader;
 
 impl Reader {
-    fn read_key(&self) -> crossterm::Result<KeyEvent> {
+    fn read_event(&self) -> crossterm::Result<Event> {
         loop {
             if event::poll(Duration::from_millis(500))? {
-                if let Event::Key(event) = event::read()? {
-                    return Ok(event);
-                }
+                return event::read();
             }
         }
     }
+
+    /** 最多等待 timeout，没有事件时返回 None */
+    fn poll_event(&self, timeout: Duration) -> crossterm::Result<Option<Event>> {
+        if event::poll(timeout)? {
+            event::read().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 struct Editor {
@@ -634,19 +3931,283 @@ struct Editor {
 }
 
 impl Editor {
-    fn new() -> Self {
-        Self {
+    fn new() -> crossterm::Result<Self> {
+        Ok(Self {
             reader: Reader,
-            output: Output::new(),
+            output: Output::new()?,
             quit_times: QUIT_TIMES, // 退出确认。在修改过后，未保存则会提出提示
+        })
+    }
+
+    /** 执行 Ctrl-E 输入的命令 */
+    fn run_command(&mut self, command: &str) -> crossterm::Result<()> {
+        let mut args = command.split_whitespace();
+        match (args.next(), args.next()) {
+            // 文件浏览器中的各行是目录的内容，只能使用不修改内容的命令
+            (Some(name), _)
+                if self.output.editor_rows.directory.is_some()
+                    && !matches!(
+                        name,
+                        "set" | "grep" | "replace" | "results" | "open" | "browse" | "goto"
+                    ) =>
+            {
+                self.output
+                    .status_message
+                    .set_message(format!("Cannot run {} in the file browser", name))
+            }
+            // 大文件未修改的部分按原样拷贝，不能换成另一种编码保存
+            (Some("encoding"), Some(_)) if self.output.editor_rows.large_file.is_some() => self
+                .output
+                .status_message
+                .set_message("Cannot change the encoding of a large file".into()),
+            (Some("encoding"), Some(name)) => match Encoding::from_name(name) {
+                Some(encoding) => {
+                    self.output.editor_rows.encoding = encoding;
+                    self.output.dirty += 1;
+                    self.output
+                        .status_message
+                        .set_message(format!("Will save as {}", encoding.name()));
+                }
+                None => self
+                    .output
+                    .status_message
+                    .set_message(format!("Unknown encoding: {}", name)),
+            },
+            (Some("hex"), None) => match self.output.editor_rows.toggle_hex() {
+                Ok(()) => {
+                    self.output.cursor_controller.cursor_x = 0;
+                    self.output.cursor_controller.cursor_y = 0;
+                }
+                Err(err) => self.output.status_message.set_message(err.to_string()),
+            },
+            (Some("set"), Some(key)) => {
+                let value = args.next().unwrap_or("on");
+                match self.output.config.set(key, value) {
+                    Ok(()) => {
+                        self.output.apply_mouse_capture()?;
+                        // 手动设置的缩进方式同样作用于当前缓冲区
+                        if let Some(indent) = &mut self.output.editor_rows.indent {
+                            match key {
+                                "expand_tabs" => indent.use_tabs = !self.output.config.expand_tabs,
+                                "indent_width" => indent.width = self.output.config.indent_width,
+                                _ => {}
+                            }
+                        }
+                        self.output
+                            .status_message
+                            .set_message(format!("{} = {}", key, value));
+                    }
+                    Err(err) => self.output.status_message.set_message(err),
+                }
+            }
+            (Some("reopen"), Some(name)) => {
+                let encoding = match Encoding::from_name(name) {
+                    Some(encoding) => encoding,
+                    None => {
+                        self.output
+                            .status_message
+                            .set_message(format!("Unknown encoding: {}", name));
+                        return Ok(());
+                    }
+                };
+                let filename = match &self.output.editor_rows.filename {
+                    Some(filename) => filename.clone(),
+                    None => {
+                        self.output
+                            .status_message
+                            .set_message("No file to reopen".into());
+                        return Ok(());
+                    }
+                };
+                if self.output.dirty > 0 {
+                    self.output
+                        .status_message
+                        .set_message("File has unsaved changes, save it first".into());
+                    return Ok(());
+                }
+                match EditorRows::from_file(filename, Some(encoding)) {
+                    Ok(editor_rows) => {
+                        self.output.set_buffer(editor_rows);
+                        self.output
+                            .status_message
+                            .set_message(format!("Reopened as {}", encoding.name()));
+                    }
+                    Err(err) => self.output.status_message.set_message(err.to_string()),
+                }
+            }
+            (Some("sort"), _) => {
+                match SortOptions::parse(command.split_whitespace().skip(1)) {
+                    Ok(options) => self
+                        .output
+                        .transform_rows(|lines| transform::sort_lines(lines, options)),
+                    Err(err) => self.output.status_message.set_message(err),
+                }
+            }
+            (Some("uniq"), all @ (None | Some("all"))) => self
+                .output
+                .transform_rows(|lines| transform::dedup_lines(lines, all.is_some())),
+            (Some("case"), Some(name)) => match Case::from_name(name) {
+                Some(case) => self.output.change_case(case),
+                None => self
+                    .output
+                    .status_message
+                    .set_message(format!("Unknown case: {} (upper, lower, title, snake, camel)", name)),
+            },
+            (Some("fill"), Some(fill)) if fill.chars().count() == 1 => {
+                self.output.fill_column(fill.chars().next().unwrap())
+            }
+            (Some("fold"), None) => self.output.fold_at_cursor(),
+            (Some("fold"), Some("all")) => match args.next().unwrap_or("0").parse() {
+                Ok(level) => self.output.fold_all(level),
+                Err(_) => self
+                    .output
+                    .status_message
+                    .set_message("Usage: fold all [level]".into()),
+            },
+            (Some("unfold"), None) => self.output.unfold_at_cursor(),
+            (Some("unfold"), Some("all")) => self.output.editor_rows.folds.clear(),
+            (Some("grep"), Some(_)) => {
+                let pattern = command.trim_start()["grep".len()..].trim().to_string();
+                self.output.search_project(pattern)?
+            }
+            // 按空白分隔参数，缺少替换内容或其中有空格时在提示中输入，没有参数时逐个询问
+            (Some("replace"), pattern) => match (pattern, args.next(), args.next()) {
+                (Some(pattern), Some(replacement), None) => {
+                    self.output.replace_in_project(pattern, replacement)?
+                }
+                (pattern, replacement, _) => self
+                    .output
+                    .prompt_replace_in_project(pattern, replacement.is_some())?,
+            },
+            (Some("hunk"), Some(direction @ ("next" | "prev"))) => {
+                self.output.jump_to_hunk(direction == "next")
+            }
+            (Some("hunk"), Some("revert")) => self.output.revert_hunk(),
+            (Some("results"), None) => self.output.show_search_results()?,
+            (Some("undo"), None) => self.output.undo(false),
+            (Some("redo"), None) => self.output.undo(true),
+            (Some("diff"), None) => self.output.show_diff(false)?,
+            (Some("diff"), Some("split")) => self.output.show_diff(true)?,
+            (Some("open"), Some(path)) => {
+                self.output.open_path(path.into());
+            }
+            (Some("browse"), dir) => {
+                let dir = match (dir, &self.output.editor_rows.filename) {
+                    (Some(dir), _) => PathBuf::from(dir),
+                    (None, Some(filename)) => filename
+                        .parent()
+                        .filter(|it| !it.as_os_str().is_empty())
+                        .map_or_else(|| PathBuf::from("."), PathBuf::from),
+                    (None, None) => PathBuf::from("."),
+                };
+                let entry = self
+                    .output
+                    .editor_rows
+                    .filename
+                    .as_ref()
+                    .and_then(|it| it.file_name())
+                    .map(|it| it.to_string_lossy().into_owned());
+                self.output.browse(dir, entry.as_deref())
+            }
+            (Some("mark"), name) => self.output.set_bookmark(name),
+            (Some("unmark"), name) => self.output.remove_bookmark(name),
+            (Some("jump"), Some(name)) => self.output.jump_to_bookmark(name),
+            (Some("marks"), None) => self.output.list_bookmarks(),
+            (Some(direction @ ("back" | "forward")), None) => {
+                self.output.jump_back(direction == "forward")
+            }
+            (Some("goto"), Some(line)) => self.output.goto_line(line),
+            (Some("duplicate"), None) => self.output.duplicate_rows(),
+            (Some("move"), Some(direction @ ("up" | "down"))) => {
+                self.output.move_rows(direction == "up")
+            }
+            (Some("delete"), None) => self.output.delete_rows(),
+            (Some("join"), None) => self.output.join_rows(),
+            (Some("indent"), None) => self.output.shift_rows(true),
+            (Some("dedent"), None) => self.output.shift_rows(false),
+            (Some("comment"), None) => self.output.toggle_line_comment(),
+            (Some("comment"), Some("block")) => self.output.toggle_block_comment(),
+            (Some(name @ ("tabs" | "spaces")), None) => {
+                let message = match self.output.convert_indent(name == "tabs") {
+                    Ok(()) => format!("Converted indentation to {}", name),
+                    Err(err) => err,
+                };
+                self.output.status_message.set_message(message);
+            }
+            _ => self.output.status_message.set_message(format!(
+                "Unknown command: {} (try: open <path>, browse [dir], grep <text>, results, replace [text] [with], hunk next|prev|revert, diff [split], undo, redo, encoding <name>, reopen <name>, hex, tabs, spaces, goto <line>, mark [name], unmark [name], jump <name>, marks, back, forward, fold [all [level]], unfold [all], fill <char>, sort, uniq, case <name>, duplicate, move up|down, delete, join, indent, dedent, comment [block], set <option> <value>)",
+                command
+            )),
+        }
+        Ok(())
+    }
+
+    /** 多光标只支持输入、删除和方向键，其他按键会取消多余的光标 */
+    fn keeps_extra_cursors(key: &KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('n') => key.modifiers == KeyModifiers::CONTROL,
+            KeyCode::Char(_) => (key.modifiers - KeyModifiers::SHIFT).is_empty(),
+            KeyCode::Up | KeyCode::Down => {
+                key.modifiers.is_empty()
+                    || key.modifiers == KeyModifiers::CONTROL | KeyModifiers::ALT
+            }
+            KeyCode::Left
+            | KeyCode::Right
+            | KeyCode::Home
+            | KeyCode::End
+            | KeyCode::Enter
+            | KeyCode::Tab
+            | KeyCode::Backspace
+            | KeyCode::Delete => key.modifiers.is_empty(),
+            _ => false,
         }
     }
 
-    fn process_keypress(&mut self) -> crossterm::Result<bool> {
-        match self.reader.read_key()? {
+    /** 普通字符的输入，连续输入时合并为一步撤销 */
+    fn is_typing(event: &Event) -> bool {
+        matches!(
+            event,
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers,
+                ..
+            }) if (*modifiers - KeyModifiers::SHIFT).is_empty() && !ch.is_whitespace()
+        )
+    }
+
+    fn process_keypress(&mut self, event: Event) -> crossterm::Result<bool> {
+        let key = match event {
+            Event::Key(key) => key,
+            Event::Resize(columns, rows) => {
+                self.output.resize(columns, rows);
+                return Ok(true);
+            }
+            Event::Mouse(event) => {
+                self.output.handle_mouse(event);
+                return Ok(true);
+            }
+            Event::Paste(_) if self.output.editor_rows.directory.is_some() => return Ok(true),
+            Event::Paste(text) => {
+                self.output.follow_cursor();
+                self.output.column_to_cursors();
+                self.output.edit_at_cursors(|output| output.paste(&text));
+                return Ok(true);
+            }
+            Event::FocusGained | Event::FocusLost => return Ok(true),
+        };
+        self.output.follow_cursor();
+        if !Self::keeps_extra_cursors(&key) {
+            self.output.cursor_controller.extra_cursors.clear();
+        }
+        if self.output.hex_keypress(key)? || self.output.browser_keypress(key)? {
+            self.quit_times = QUIT_TIMES;
+            return Ok(true);
+        }
+        match key {
             KeyEvent {
                 code: KeyCode::Char('q'),
                 modifiers: KeyModifiers::CONTROL,
+                ..
             } => {
                 if self.output.dirty > 0 && self.quit_times > 0 {
                     self.output.status_message.set_message(format!(
@@ -666,20 +4227,166 @@ impl Editor {
                     | KeyCode::Right
                     | KeyCode::Home
                     | KeyCode::End),
-                modifiers: KeyModifiers::NONE,
-            } => self.output.move_cursor(direction),
+                modifiers,
+                ..
+            } if modifiers == KeyModifiers::NONE || modifiers == KeyModifiers::SHIFT => {
+                self.output
+                    .cursor_controller
+                    .update_selection(modifiers == KeyModifiers::SHIFT);
+                self.output.move_extra_cursors(direction);
+                self.output.move_cursor(direction)
+            }
+            KeyEvent {
+                code:
+                    direction @ (KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Home
+                    | KeyCode::End),
+                modifiers,
+                ..
+            } if modifiers == KeyModifiers::ALT | KeyModifiers::SHIFT => {
+                // Alt-Shift 加方向键进行列选择
+                self.output.extend_column_selection(direction)
+            }
+            KeyEvent {
+                code: KeyCode::Char(ch @ ('c' | 'x')),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.copy(ch == 'x'),
+            KeyEvent {
+                code: KeyCode::Char('v'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.paste_clipboard(),
+            KeyEvent {
+                code: direction @ (KeyCode::Up | KeyCode::Down),
+                modifiers,
+                ..
+            } if modifiers == KeyModifiers::CONTROL | KeyModifiers::ALT => {
+                self.output.add_cursor_vertical(direction == KeyCode::Up)
+            }
+            KeyEvent {
+                code: KeyCode::Char('n'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.add_cursor_at_next_occurrence(),
+            KeyEvent {
+                code: direction @ (KeyCode::Left | KeyCode::Right),
+                modifiers,
+                ..
+            } if modifiers - KeyModifiers::SHIFT == KeyModifiers::CONTROL => {
+                self.output
+                    .cursor_controller
+                    .update_selection(modifiers.contains(KeyModifiers::SHIFT));
+                self.output.move_word(direction == KeyCode::Right)
+            }
+            KeyEvent {
+                code: direction @ (KeyCode::Left | KeyCode::Right),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.jump_back(direction == KeyCode::Right),
+            KeyEvent {
+                code: KeyCode::Char('g'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                if let Some(line) = prompt!(&mut self.output, "Go to line: {} (ESC to cancel)") {
+                    self.output.goto_line(&line)
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char('p'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.find_file()?,
+            KeyEvent {
+                code: KeyCode::Char('o'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                if let Some(path) = prompt!(
+                    &mut self.output,
+                    "Open: {} (Tab to complete, ESC to cancel)",
+                    complete = |input: &str| Some(browser::complete(input))
+                ) {
+                    self.output.open_path(path.into());
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char('m'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.toggle_bookmark(),
+            KeyEvent {
+                code: KeyCode::Char(ch @ ('.' | ',')),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.jump_to_hunk(ch == '.'),
+            KeyEvent {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.prompt_replace_in_project(None, false)?,
+            KeyEvent {
+                code: KeyCode::Char('g'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => {
+                if let Some(pattern) =
+                    prompt!(&mut self.output, "Search project: {} (ESC to cancel)")
+                {
+                    self.output.search_project(pattern)?
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char(digit @ '1'..='9'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.jump_to_bookmark(&digit.to_string()),
+            KeyEvent {
+                code: KeyCode::Char(ch @ ('b' | 'f')),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => {
+                self.output.cursor_controller.update_selection(false);
+                self.output.move_word(ch == 'f')
+            }
+            // 不绑定 Ctrl-H，有些终端把 Backspace 发送为 Ctrl-H
+            KeyEvent {
+                code: KeyCode::Backspace,
+                modifiers: KeyModifiers::CONTROL | KeyModifiers::ALT,
+                ..
+            } => self.output.delete_word(false),
+            KeyEvent {
+                code: KeyCode::Delete,
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Char('d'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.delete_word(true),
             KeyEvent {
                 code: val @ (KeyCode::PageUp | KeyCode::PageDown),
                 modifiers: KeyModifiers::NONE,
+                ..
             } => {
+                self.output.record_jump();
+                self.output.cursor_controller.selection_anchor = None;
                 if matches!(val, KeyCode::PageUp) {
                     self.output.cursor_controller.cursor_y =
                         self.output.cursor_controller.row_offset
                 } else {
-                    self.output.cursor_controller.cursor_y = cmp::min(
-                        self.output.win_size.1 + self.output.cursor_controller.row_offset - 1,
-                        self.output.editor_rows.number_of_rows(),
-                    );
+                    let cursor_controller = &mut self.output.cursor_controller;
+                    let last_on_screen = (1..self.output.win_size.1)
+                        .fold(cursor_controller.row_offset, |y, _| {
+                            self.output.editor_rows.folds.next_visible(y)
+                        });
+                    cursor_controller.cursor_y =
+                        cmp::min(last_on_screen, self.output.editor_rows.number_of_rows());
                 }
                 (0..self.output.win_size.1).for_each(|_| {
                     self.output.move_cursor(if matches!(val, KeyCode::PageUp) {
@@ -689,14 +4396,25 @@ impl Editor {
                     });
                 })
             }
+            KeyEvent {
+                code: KeyCode::Esc,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => self.output.cursor_controller.update_selection(false),
+            KeyEvent {
+                code: KeyCode::Char(ch @ ('z' | 'y')),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.undo(ch == 'y'),
             KeyEvent {
                 code: KeyCode::Char('s'),
                 modifiers: KeyModifiers::CONTROL,
+                ..
             } => {
-                if matches!(self.output.editor_rows.filename, None) {
+                if self.output.editor_rows.filename.is_none() {
                     let prompt = prompt!(&mut self.output, "Save as : {} (ESC to cancel)")
                         .map(|it| it.into());
-                    if let None = prompt {
+                    if prompt.is_none() {
                         self.output
                             .status_message
                             .set_message("Save Aborted".into());
@@ -704,57 +4422,168 @@ impl Editor {
                     }
                     self.output.editor_rows.filename = prompt
                 }
-                self.output.editor_rows.save().map(|len| {
-                    self.output
+                match self.output.editor_rows.save() {
+                    Ok(len) => {
+                        self.output
+                            .status_message
+                            .set_message(format!("{} bytes written to disk", len));
+                        self.output.dirty = 0;
+                        // 书签的行号随编辑移动，保存文件时一并更新
+                        self.output.save_bookmarks();
+                        // 保存到新的文件名时，或文件在外部被提交后，需要重新读取 HEAD 中的版本
+                        let editor_rows = &mut self.output.editor_rows;
+                        if editor_rows.large_file.is_none() && editor_rows.hex_view.is_none() {
+                            editor_rows.git =
+                                editor_rows.filename.as_deref().and_then(GitGutter::load);
+                        }
+                    }
+                    // 编码失败时提示用户而不是退出编辑器
+                    Err(err) if err.kind() == ErrorKind::InvalidData => self
+                        .output
                         .status_message
-                        .set_message(format!("{} bytes written to disk", len));
-                    self.output.dirty = 0
-                })?;
+                        .set_message(format!("Save failed: {}", err)),
+                    Err(err) => return Err(err),
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char('e'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                if let Some(command) = prompt!(&mut self.output, "Command: {} (ESC to cancel)") {
+                    self.run_command(&command)?;
+                }
             }
             /* add the following*/
             KeyEvent {
                 code: KeyCode::Char('f'),
                 modifiers: KeyModifiers::CONTROL,
+                ..
             } => {
                 self.output.find()?;
             }
+            KeyEvent {
+                code: KeyCode::Char('b'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.jump_to_matching_bracket(),
+            KeyEvent {
+                code: KeyCode::Char('d'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.duplicate_rows(),
+            KeyEvent {
+                code: direction @ (KeyCode::Up | KeyCode::Down),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.move_rows(direction == KeyCode::Up),
+            KeyEvent {
+                code: KeyCode::Char('k'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.delete_rows(),
+            KeyEvent {
+                code: KeyCode::Char('j'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.join_rows(),
+            // 多数终端把 Ctrl-/ 发送为 Ctrl-7
+            KeyEvent {
+                code: KeyCode::Char('/' | '7'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.toggle_line_comment(),
             KeyEvent {
                 code: key @ (KeyCode::Backspace | KeyCode::Delete),
                 modifiers: KeyModifiers::NONE,
+                ..
             } => {
-                if matches!(key, KeyCode::Delete) {
-                    self.output.move_cursor(KeyCode::Right)
+                if self.output.delete_selection() {
+                    return Ok(true);
                 }
-                self.output.delete_char()
+                self.output.edit_at_cursors(|output| {
+                    if matches!(key, KeyCode::Delete) {
+                        output.move_cursor(KeyCode::Right);
+                        output.delete_char()
+                    } else {
+                        output.backspace()
+                    }
+                })
             }
             KeyEvent {
                 code: KeyCode::Enter,
                 modifiers: KeyModifiers::NONE,
-            } => self.output.insert_newline(),
+                ..
+            } => self.output.edit_at_cursors(Output::insert_newline),
+            KeyEvent {
+                code: KeyCode::Tab,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } if self.output.cursor_controller.selection().is_some() => {
+                self.output.shift_rows(true)
+            }
+            KeyEvent {
+                code: KeyCode::BackTab,
+                ..
+            } => self.output.shift_rows(false),
             KeyEvent {
                 code: code @ (KeyCode::Char(..) | KeyCode::Tab),
                 modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
-            } => self.output.insert_char(match code {
-                KeyCode::Tab => '\t',
-                KeyCode::Char(ch) => ch,
-                _ => unreachable!(),
-            }),
+                ..
+            } => {
+                let ch = match code {
+                    KeyCode::Tab => '\t',
+                    KeyCode::Char(ch) => ch,
+                    _ => unreachable!(),
+                };
+                self.output.column_to_cursors();
+                self.output.edit_at_cursors(|output| output.type_char(ch))
+            }
             _ => {}
         }
         self.quit_times = QUIT_TIMES;
         Ok(true)
     }
 
+    /** 等待下一个事件。git 修改标记过期时先等待 GIT_DELAY，期间没有输入才重新比较并刷新屏幕 */
+    fn read_event(&mut self) -> crossterm::Result<Event> {
+        if self.output.git_gutter_outdated() {
+            if let Some(event) = self.reader.poll_event(GIT_DELAY)? {
+                return Ok(event);
+            }
+            self.output.update_git_gutter();
+            self.output.refresh_screen()?;
+        }
+        self.reader.read_event()
+    }
+
     fn run(&mut self) -> crossterm::Result<bool> {
         self.output.refresh_screen()?;
-        self.process_keypress()
+        self.output.start_history();
+        let event = self.read_event()?;
+        let typing = Self::is_typing(&event);
+        if !typing {
+            // 其他事件不与前面连续输入的字符合并
+            self.output.commit_history();
+        }
+        let (dirty, cursor) = (self.output.dirty, self.output.cursor_position());
+        let protected = self.output.protect_undecodable();
+        let running = self.process_keypress(event)?;
+        if let Some(window_dirty) = protected {
+            self.output.revert_undecodable(window_dirty, dirty, cursor);
+        }
+        self.output
+            .record_history(self.output.dirty != dirty, typing, cursor);
+        Ok(running)
     }
 }
 
 fn main() -> crossterm::Result<()> {
     let _clean_up = CleanUp; // 当程序结束后就会执行其中的 drop
     terminal::enable_raw_mode()?;
-    let mut editor = Editor::new();
+    let mut editor = Editor::new()?;
+    editor.output.apply_mouse_capture()?;
+    execute!(stdout(), EnableBracketedPaste)?;
     while editor.run()? {}
     Ok(())
 }
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+/** 书签文件 ~/.pound_bookmarks，每行 "文件的绝对路径\t名称\t行\t列" */
+const BOOKMARKS_FILE: &str = ".pound_bookmarks";
+/** 跳转历史最多保留的位置数 */
+const MAX_JUMPS: usize = 100;
+
+/** 书签和跳转历史，位置都是 (x, y)，行号随上方插入和删除的行移动 */
+#[derive(Default)]
+pub struct Marks {
+    bookmarks: BTreeMap<String, (usize, usize)>,
+    jumps: Vec<(usize, usize)>,
+    /** 在 jumps 中的位置，等于 jumps.len() 时表示不在回退过程中 */
+    current: usize,
+}
+
+impl Marks {
+    /** 读取 file 的书签，书签文件不存在或无法解析的行会被忽略 */
+    pub fn load(file: &Path) -> Self {
+        let mut marks = Self::default();
+        let key = Self::key(file);
+        let contents = match Self::path().and_then(|path| fs::read_to_string(path).ok()) {
+            Some(contents) => contents,
+            None => return marks,
+        };
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if let [path, name, y, x] = fields[..] {
+                if let (true, Ok(y), Ok(x)) = (path == key, y.parse(), x.parse()) {
+                    marks.bookmarks.insert(name.into(), (x, y));
+                }
+            }
+        }
+        marks
+    }
+
+    /** 用当前书签替换书签文件中 file 的记录 */
+    pub fn save(&self, file: &Path) -> io::Result<()> {
+        let path = Self::path().ok_or_else(|| io::Error::other("HOME is not set"))?;
+        let key = Self::key(file);
+        let existing = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        let mut contents: String = existing
+            .lines()
+            .filter(|line| line.split('\t').next() != Some(key.as_str()))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        for (name, (x, y)) in &self.bookmarks {
+            contents.push_str(&format!("{}\t{}\t{}\t{}\n", key, name, y, x));
+        }
+        fs::write(path, contents)
+    }
+
+    fn path() -> Option<PathBuf> {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(BOOKMARKS_FILE))
+    }
+
+    /** 书签文件中用绝对路径区分文件，新文件还不存在时使用原路径 */
+    fn key(file: &Path) -> String {
+        fs::canonicalize(file)
+            .unwrap_or_else(|_| file.to_path_buf())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn set(&mut self, name: &str, position: (usize, usize)) {
+        self.bookmarks.insert(name.into(), position);
+    }
+
+    pub fn get(&self, name: &str) -> Option<(usize, usize)> {
+        self.bookmarks.get(name).copied()
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.bookmarks.remove(name).is_some()
+    }
+
+    /** 去掉第 y 行上的书签，返回去掉的个数 */
+    pub fn remove_on_row(&mut self, y: usize) -> usize {
+        let before = self.bookmarks.len();
+        self.bookmarks.retain(|_, &mut (_, row)| row != y);
+        before - self.bookmarks.len()
+    }
+
+    /** 最小的未使用的编号 */
+    pub fn next_number(&self) -> String {
+        (1..)
+            .map(|n: usize| n.to_string())
+            .find(|name| !self.bookmarks.contains_key(name))
+            .unwrap()
+    }
+
+    /** 按名称排列的所有书签 */
+    pub fn bookmarks(&self) -> impl Iterator<Item = (&str, (usize, usize))> {
+        self.bookmarks
+            .iter()
+            .map(|(name, &position)| (name.as_str(), position))
+    }
+
+    /** 大范围移动光标之前记录原来的位置，会丢弃回退过程中经过的位置 */
+    pub fn record_jump(&mut self, position: (usize, usize)) {
+        self.jumps.truncate(self.current);
+        if self.jumps.last() != Some(&position) {
+            self.jumps.push(position);
+        }
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.current = self.jumps.len();
+    }
+
+    /** 回到上一个位置，position 为当前位置，以便之后再前进回来 */
+    pub fn back(&mut self, position: (usize, usize)) -> Option<(usize, usize)> {
+        if self.current == 0 {
+            return None;
+        }
+        if self.current == self.jumps.len() {
+            self.jumps.push(position);
+        }
+        self.current -= 1;
+        Some(self.jumps[self.current])
+    }
+
+    pub fn forward(&mut self) -> Option<(usize, usize)> {
+        if self.current + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.current += 1;
+        Some(self.jumps[self.current])
+    }
+
+    fn map_rows(&mut self, f: impl Fn(usize) -> usize) {
+        for (_, y) in self.bookmarks.values_mut().chain(self.jumps.iter_mut()) {
+            *y = f(*y);
+        }
+    }
+
+    /** 在 at 处插入了 count 行 */
+    pub fn rows_inserted(&mut self, at: usize, count: usize) {
+        self.map_rows(|y| if y >= at { y + count } else { y });
+    }
+
+    /** 删除了 rows 中的行，其中的位置移到删除后的下一行 */
+    pub fn rows_removed(&mut self, rows: Range<usize>) {
+        self.map_rows(|y| match y {
+            y if y >= rows.end => y - rows.len(),
+            y if y >= rows.start => rows.start,
+            y => y,
+        });
+    }
+
+    /** 第 at 行被连接到上一行 */
+    pub fn row_joined(&mut self, at: usize) {
+        self.map_rows(|y| if y >= at { y - 1 } else { y });
+    }
+
+    /** rows 中的行被替换成了 count 行 */
+    pub fn rows_replaced(&mut self, rows: Range<usize>, count: usize) {
+        let last = rows.start + count.saturating_sub(1);
+        self.map_rows(|y| match y {
+            y if y >= rows.end => y + count - rows.len(),
+            y if y >= rows.start => y.min(last),
+            y => y,
+        });
+    }
+
+    /** rows 中的第一行移到了末尾（up）或最后一行移到了开头 */
+    pub fn rows_rotated(&mut self, rows: Range<usize>, up: bool) {
+        self.map_rows(|y| match y {
+            y if !rows.contains(&y) => y,
+            y if up && y == rows.start => rows.end - 1,
+            y if up => y - 1,
+            y if y == rows.end - 1 => rows.start,
+            y => y + 1,
+        });
+    }
+}
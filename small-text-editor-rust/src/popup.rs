@@ -0,0 +1,51 @@
+use std::cmp;
+
+use crossterm::event::KeyCode;
+
+/** 显示在文本区域底部的列表，用方向键选择其中一项 */
+pub struct Popup {
+    /** 显示在列表上方的标题 */
+    pub title: String,
+    pub items: Vec<String>,
+    pub selected: usize,
+    /** 列表滚动到的第一项 */
+    offset: usize,
+}
+
+impl Popup {
+    pub fn new(title: String, items: Vec<String>) -> Self {
+        Self {
+            title,
+            items,
+            selected: 0,
+            offset: 0,
+        }
+    }
+
+    pub fn selected_item(&self) -> Option<&str> {
+        self.items.get(self.selected).map(String::as_str)
+    }
+
+    /** 按方向键或翻页键移动选中项，page 为一页的行数，返回按键是否被处理 */
+    pub fn move_selection(&mut self, key: KeyCode, page: usize) -> bool {
+        let last = self.items.len().saturating_sub(1);
+        self.selected = match key {
+            KeyCode::Up => self.selected.saturating_sub(1),
+            KeyCode::Down => cmp::min(self.selected + 1, last),
+            KeyCode::PageUp => self.selected.saturating_sub(page),
+            KeyCode::PageDown => cmp::min(self.selected + page, last),
+            _ => return false,
+        };
+        true
+    }
+
+    /** 可以显示 height 项时，调整滚动位置使选中项可见，返回要显示的各项及其下标 */
+    pub fn visible(&mut self, height: usize) -> impl Iterator<Item = (usize, &String)> {
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+        self.items.iter().enumerate().skip(self.offset).take(height)
+    }
+}
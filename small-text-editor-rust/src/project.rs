@@ -0,0 +1,45 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+
+/** 列出项目文件时最多返回的文件数 */
+const MAX_FILES: usize = 100_000;
+
+/** 项目根目录：当前目录或其上级目录中最近的包含 .git 的一个，都没有时为当前目录 */
+pub fn root() -> PathBuf {
+    let current = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    current
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .unwrap_or(&current)
+        .to_path_buf()
+}
+
+/** 遍历项目中的文件，跳过隐藏文件和 .gitignore 忽略的文件 */
+pub fn walk(root: &Path) -> impl Iterator<Item = PathBuf> {
+    WalkBuilder::new(root)
+        .require_git(false)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|it| it.is_file()))
+        .map(|entry| entry.into_path())
+}
+
+/** path 相对于 root 的路径，以 / 分隔 */
+pub fn relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|it| it.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/** 项目中所有文件相对于 root 的路径 */
+pub fn files(root: &Path) -> Vec<String> {
+    walk(root)
+        .take(MAX_FILES)
+        .map(|path| relative(root, &path))
+        .collect()
+}
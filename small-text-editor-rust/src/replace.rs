@@ -0,0 +1,414 @@
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::grep;
+use crate::project;
+
+/** 一行中要替换的内容，line 从 0 开始 */
+pub struct LineChange {
+    pub line: usize,
+    pub text: String,
+    /** text 中各处匹配的字节范围，互不重叠且从前往后排列 */
+    pub matches: Vec<Range<usize>>,
+    /** 预览时每处匹配是否选中，只替换选中的匹配 */
+    pub included: Vec<bool>,
+}
+
+impl LineChange {
+    /** 选中的匹配，从前往后排列 */
+    pub fn included_matches(&self) -> impl DoubleEndedIterator<Item = &Range<usize>> {
+        self.matches
+            .iter()
+            .zip(&self.included)
+            .filter(|(_, &included)| included)
+            .map(|(range, _)| range)
+    }
+
+    pub fn is_included(&self) -> bool {
+        self.included.contains(&true)
+    }
+
+    /** 替换选中的匹配后的内容 */
+    pub fn replaced(&self, replacement: &str) -> String {
+        let mut result = self.text.clone();
+        for range in self.included_matches().rev() {
+            result.replace_range(range.clone(), replacement);
+        }
+        result
+    }
+}
+
+/** 一个文件中所有要替换的行 */
+pub struct FileChanges {
+    /** 相对于项目根目录的路径 */
+    pub path: String,
+    pub lines: Vec<LineChange>,
+}
+
+/** text 中 pattern 的所有不重叠的匹配 */
+fn find_all(text: &str, pattern: &str, whole_word: bool) -> Vec<Range<usize>> {
+    let mut matches = Vec::new();
+    if pattern.is_empty() {
+        return matches;
+    }
+    let mut start = 0;
+    while let Some(index) = crate::find_keyword(&text[start..], pattern, whole_word) {
+        let index = start + index;
+        matches.push(index..index + pattern.len());
+        start = index + pattern.len();
+    }
+    matches
+}
+
+/** 找出 lines 中包含 pattern 的行 */
+pub fn find_lines<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    pattern: &str,
+    whole_word: bool,
+) -> Vec<LineChange> {
+    lines
+        .enumerate()
+        .filter_map(|(line, text)| {
+            let matches = find_all(text, pattern, whole_word);
+            (!matches.is_empty()).then(|| LineChange {
+                line,
+                text: text.into(),
+                included: vec![true; matches.len()],
+                matches,
+            })
+        })
+        .collect()
+}
+
+/** 找出项目中所有包含 pattern 的行，skip 为正在编辑的文件，由调用者使用内存中的内容 */
+pub fn collect(
+    root: &Path,
+    pattern: &str,
+    whole_word: bool,
+    skip: Option<&Path>,
+) -> Vec<FileChanges> {
+    project::walk(root)
+        .filter(|path| skip.is_none_or(|skip| fs::canonicalize(path).ok().as_deref() != Some(skip)))
+        .filter_map(|path| {
+            let (_, contents) = grep::read_text(&path)?;
+            let lines = find_lines(contents.lines(), pattern, whole_word);
+            (!lines.is_empty()).then(|| FileChanges {
+                path: project::relative(root, &path),
+                lines,
+            })
+        })
+        .collect()
+}
+
+/** 生成替换后的文件内容，文件在预览之后被修改过时返回错误 */
+fn replaced_contents(root: &Path, file: &FileChanges, replacement: &str) -> io::Result<Vec<u8>> {
+    let changed = || io::Error::other(format!("{} changed since the preview", file.path));
+    let (encoding, contents) = grep::read_text(&root.join(&file.path)).ok_or_else(changed)?;
+    let mut changes = file.lines.iter().filter(|it| it.is_included()).peekable();
+    let mut result = String::with_capacity(contents.len());
+    for (i, line) in contents.split_inclusive('\n').enumerate() {
+        // 与 str::lines 一样去掉 \n 或 \r\n，换行符原样保留
+        let ending = if line.ends_with("\r\n") {
+            "\r\n"
+        } else if line.ends_with('\n') {
+            "\n"
+        } else {
+            ""
+        };
+        let text = &line[..line.len() - ending.len()];
+        match changes.next_if(|change| change.line == i) {
+            Some(change) if change.text == text => {
+                result.push_str(&change.replaced(replacement));
+                result.push_str(ending);
+            }
+            Some(_) => return Err(changed()),
+            None => result.push_str(line),
+        }
+    }
+    if changes.next().is_some() {
+        return Err(changed());
+    }
+    encoding.encode(&result)
+}
+
+/**
+ * 把选中的替换写入磁盘上的文件。先为所有文件生成新内容，任何一个失败都不会写入；
+ * 再把所有文件写到同目录下的临时文件，全部写入成功后才逐个改名替换原文件，
+ * 改名失败时错误信息中列出已经替换的文件
+ */
+pub fn apply(root: &Path, files: &[FileChanges], replacement: &str) -> io::Result<()> {
+    let mut contents: Vec<(&str, PathBuf, Vec<u8>)> = Vec::new();
+    for file in files {
+        if file.lines.iter().any(LineChange::is_included) {
+            contents.push((
+                &file.path,
+                root.join(&file.path),
+                replaced_contents(root, file, replacement)?,
+            ));
+        }
+    }
+    let mut temporaries: Vec<(&str, PathBuf, PathBuf)> = Vec::new();
+    for (name, path, bytes) in contents {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temporary = path.with_file_name(format!(".{}.pound-replace", file_name));
+        let written = fs::write(&temporary, bytes).and_then(|_| {
+            fs::metadata(&path).and_then(|it| fs::set_permissions(&temporary, it.permissions()))
+        });
+        temporaries.push((name, temporary, path));
+        if let Err(err) = written {
+            for (_, temporary, _) in &temporaries {
+                let _ = fs::remove_file(temporary);
+            }
+            return Err(err);
+        }
+    }
+    let mut renamed = Vec::new();
+    let mut temporaries = temporaries.into_iter();
+    while let Some((name, temporary, path)) = temporaries.next() {
+        if let Err(err) = fs::rename(&temporary, &path) {
+            let _ = fs::remove_file(&temporary);
+            for (_, temporary, _) in temporaries {
+                let _ = fs::remove_file(temporary);
+            }
+            let written = if renamed.is_empty() {
+                "no files were changed".to_string()
+            } else {
+                format!("already replaced in {}", renamed.join(", "))
+            };
+            return Err(io::Error::new(
+                err.kind(),
+                format!("{}: {} ({})", name, err, written),
+            ));
+        }
+        renamed.push(name);
+    }
+    Ok(())
+}
+
+/** 预览列表中的一项：文件、文件中的一行或一行中的一处匹配 */
+#[derive(Clone, Copy)]
+pub enum Target {
+    File(usize),
+    Line(usize, usize),
+    Match(usize, usize, usize),
+}
+
+/** 选中状态的标记：全部选中、部分选中或都没选中 */
+fn check_mark(included: &[bool]) -> char {
+    if included.iter().all(|&it| it) {
+        'x'
+    } else if included.contains(&true) {
+        '-'
+    } else {
+        ' '
+    }
+}
+
+/**
+ * 预览列表的各行，以及每一行对应的项。一行中有多处匹配时，
+ * 每处匹配单独列出，可以只替换其中的一部分
+ */
+pub fn preview(files: &[FileChanges], replacement: &str) -> (Vec<String>, Vec<Target>) {
+    let mut items = Vec::new();
+    let mut targets = Vec::new();
+    for (i, file) in files.iter().enumerate() {
+        let included = file.lines.iter().filter(|it| it.is_included()).count();
+        items.push(format!("{} ({}/{})", file.path, included, file.lines.len()));
+        targets.push(Target::File(i));
+        for (j, change) in file.lines.iter().enumerate() {
+            items.push(format!(
+                "  [{}] {}: {} → {}",
+                check_mark(&change.included),
+                change.line + 1,
+                change.text.trim(),
+                change.replaced(replacement).trim()
+            ));
+            targets.push(Target::Line(i, j));
+            if change.matches.len() < 2 {
+                continue;
+            }
+            for (k, range) in change.matches.iter().enumerate() {
+                let mut text = change.text.clone();
+                text.replace_range(range.clone(), replacement);
+                items.push(format!(
+                    "      [{}] column {}: {}",
+                    check_mark(&change.included[k..=k]),
+                    range.start + 1,
+                    text.trim()
+                ));
+                targets.push(Target::Match(i, j, k));
+            }
+        }
+    }
+    (items, targets)
+}
+
+/** 切换 target 的选中状态，文件或行中有没选中的匹配时全部选中，否则全部取消 */
+pub fn toggle(files: &mut [FileChanges], target: Target) {
+    let flip = |included: &mut [bool]| {
+        let include = included.contains(&false);
+        included.iter_mut().for_each(|it| *it = include);
+    };
+    match target {
+        Target::File(i) => {
+            let include = files[i].lines.iter().any(|it| it.included.contains(&false));
+            for change in &mut files[i].lines {
+                change.included.iter_mut().for_each(|it| *it = include);
+            }
+        }
+        Target::Line(i, j) => flip(&mut files[i].lines[j].included),
+        Target::Match(i, j, k) => flip(&mut files[i].lines[j].included[k..=k]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** 每个测试使用自己的临时目录 */
+    fn temporary_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("pound-replace-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn changes(path: &str, contents: &str, pattern: &str) -> FileChanges {
+        FileChanges {
+            path: path.into(),
+            lines: find_lines(contents.lines(), pattern, false),
+        }
+    }
+
+    #[test]
+    fn empty_pattern_finds_nothing() {
+        assert!(find_all("abc", "", false).is_empty());
+    }
+
+    #[test]
+    fn find_all_does_not_overlap() {
+        assert_eq!(find_all("aaaaa", "aa", false), vec![0..2, 2..4]);
+        assert_eq!(find_all("a.b.c", ".", false), vec![1..2, 3..4]);
+    }
+
+    #[test]
+    fn find_all_whole_word() {
+        assert_eq!(find_all("foo foobar foo", "foo", true), vec![0..3, 11..14]);
+        assert_eq!(
+            find_all("foo foobar foo", "foo", false),
+            vec![0..3, 4..7, 11..14]
+        );
+    }
+
+    #[test]
+    fn find_lines_reports_matching_lines() {
+        let lines = find_lines("a\nb a\nc".lines(), "a", false);
+        let found: Vec<_> = lines.iter().map(|it| (it.line, it.text.as_str())).collect();
+        assert_eq!(found, vec![(0, "a"), (1, "b a")]);
+    }
+
+    #[test]
+    fn replaced_replaces_every_match() {
+        let line = &find_lines(std::iter::once("a-a-a"), "a", false)[0];
+        assert_eq!(line.replaced("bb"), "bb-bb-bb");
+        assert_eq!(line.replaced(""), "--");
+    }
+
+    #[test]
+    fn replaced_contents_keeps_line_endings() {
+        let dir = temporary_dir("endings");
+        let contents = "foo\r\nbar\r\nfoo foo\nfoo";
+        fs::write(dir.join("crlf.txt"), contents).unwrap();
+        let file = changes("crlf.txt", contents, "foo");
+        let result = replaced_contents(&dir, &file, "x").unwrap();
+        assert_eq!(String::from_utf8(result).unwrap(), "x\r\nbar\r\nx x\nx");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replaced_contents_skips_excluded_lines() {
+        let dir = temporary_dir("excluded");
+        let contents = "foo\nfoo\n";
+        fs::write(dir.join("a.txt"), contents).unwrap();
+        let mut file = changes("a.txt", contents, "foo");
+        file.lines[0].included = vec![false];
+        let result = replaced_contents(&dir, &file, "x").unwrap();
+        assert_eq!(String::from_utf8(result).unwrap(), "foo\nx\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn excluded_matches_on_a_line_are_kept() {
+        let dir = temporary_dir("matches");
+        let contents = "foo(foo, foo)\n";
+        fs::write(dir.join("a.txt"), contents).unwrap();
+        let mut files = [changes("a.txt", contents, "foo")];
+        toggle(&mut files, Target::Match(0, 0, 1));
+        assert_eq!(files[0].lines[0].replaced("x"), "x(foo, x)");
+        let result = replaced_contents(&dir, &files[0], "x").unwrap();
+        assert_eq!(String::from_utf8(result).unwrap(), "x(foo, x)\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn toggle_selects_all_when_some_are_excluded() {
+        let mut files = [changes("a.txt", "foo foo\nfoo\n", "foo")];
+        toggle(&mut files, Target::Match(0, 0, 0));
+        assert_eq!(files[0].lines[0].included, [false, true]);
+        toggle(&mut files, Target::Line(0, 0));
+        assert_eq!(files[0].lines[0].included, [true, true]);
+        toggle(&mut files, Target::File(0));
+        assert!(!files[0].lines.iter().any(LineChange::is_included));
+        let (items, targets) = preview(&files, "x");
+        assert_eq!(items.len(), targets.len());
+        assert_eq!(items[1], "  [ ] 1: foo foo → foo foo");
+        assert_eq!(items[2], "      [ ] column 1: x foo");
+    }
+
+    #[test]
+    fn replaced_contents_detects_changed_files() {
+        let dir = temporary_dir("changed");
+        let file = changes("a.txt", "foo\n", "foo");
+        fs::write(dir.join("a.txt"), "bar\n").unwrap();
+        assert!(replaced_contents(&dir, &file, "x").is_err());
+        fs::write(dir.join("a.txt"), "").unwrap();
+        assert!(replaced_contents(&dir, &file, "x").is_err());
+        fs::remove_file(dir.join("a.txt")).unwrap();
+        assert!(replaced_contents(&dir, &file, "x").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn apply_writes_every_file() {
+        let dir = temporary_dir("apply");
+        fs::write(dir.join("a.txt"), "foo\n").unwrap();
+        fs::write(dir.join("b.txt"), "bar foo\n").unwrap();
+        let mut skipped = changes("b.txt", "bar foo\n", "foo");
+        skipped.lines[0].included = vec![false];
+        let files = [changes("a.txt", "foo\n", "foo"), skipped];
+        apply(&dir, &files, "x").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "x\n");
+        assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "bar foo\n");
+        // 不留下临时文件
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn apply_writes_nothing_when_a_file_changed() {
+        let dir = temporary_dir("apply-changed");
+        fs::write(dir.join("a.txt"), "foo\n").unwrap();
+        fs::write(dir.join("b.txt"), "changed\n").unwrap();
+        let files = [
+            changes("a.txt", "foo\n", "foo"),
+            changes("b.txt", "foo\n", "foo"),
+        ];
+        assert!(apply(&dir, &files, "x").is_err());
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "foo\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
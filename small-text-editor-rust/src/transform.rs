@@ -0,0 +1,246 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+#[derive(Clone, Copy, Default)]
+pub struct SortOptions {
+    /** 按行首的数字排序，没有数字的行排在最前 */
+    pub numeric: bool,
+    pub ignore_case: bool,
+    pub reverse: bool,
+}
+
+impl SortOptions {
+    /** 解析 "numeric nocase reverse" 这样的选项，顺序任意 */
+    pub fn parse<'a>(args: impl Iterator<Item = &'a str>) -> Result<Self, String> {
+        let mut options = Self::default();
+        for arg in args {
+            match arg {
+                "numeric" | "n" => options.numeric = true,
+                "nocase" | "i" => options.ignore_case = true,
+                "reverse" | "r" => options.reverse = true,
+                _ => return Err(format!("Unknown sort option: {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/** 行首的数字，如 "  -1.5 apples" 中的 -1.5 */
+fn leading_number(line: &str) -> Option<f64> {
+    let line = line.trim_start();
+    let end = line
+        .char_indices()
+        .find(|&(index, c)| !(c.is_ascii_digit() || c == '.' || (index == 0 && "+-".contains(c))))
+        .map_or(line.len(), |(index, _)| index);
+    line[..end].parse().ok()
+}
+
+/** 稳定排序，相等的行保持原来的顺序 */
+pub fn sort_lines(lines: &mut [String], options: SortOptions) {
+    lines.sort_by(|a, b| {
+        let ordering = if options.numeric {
+            match (leading_number(a), leading_number(b)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            }
+        } else if options.ignore_case {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        } else {
+            a.cmp(b)
+        };
+        if options.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+/** 去掉重复的行，all 为 false 时只去掉相邻的重复行 */
+pub fn dedup_lines(lines: &mut Vec<String>, all: bool) {
+    if all {
+        let mut seen = HashSet::new();
+        lines.retain(|line| seen.insert(line.clone()));
+    } else {
+        lines.dedup();
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Case {
+    Upper,
+    Lower,
+    Title,
+    Snake,
+    Camel,
+}
+
+impl Case {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "upper" => Some(Self::Upper),
+            "lower" => Some(Self::Lower),
+            "title" => Some(Self::Title),
+            "snake" => Some(Self::Snake),
+            "camel" => Some(Self::Camel),
+            _ => None,
+        }
+    }
+
+    /** 转换 text，空白保持不变；snake 和 camel 只转换其中的标识符，其他字符原样保留 */
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Self::Upper => text.to_uppercase(),
+            Self::Lower => text.to_lowercase(),
+            Self::Title => map_tokens(text, |token| {
+                let mut start_of_word = true;
+                token
+                    .chars()
+                    .flat_map(|c| {
+                        let converted: Vec<char> = if start_of_word {
+                            c.to_uppercase().collect()
+                        } else {
+                            c.to_lowercase().collect()
+                        };
+                        start_of_word = !c.is_alphanumeric();
+                        converted
+                    })
+                    .collect()
+            }),
+            Self::Snake => map_identifiers(text, |token| {
+                keep_underscores(token, |name| split_words(name).join("_"))
+            }),
+            Self::Camel => map_identifiers(text, |token| {
+                keep_underscores(token, |name| {
+                    split_words(name)
+                        .iter()
+                        .enumerate()
+                        .map(|(i, word)| {
+                            let mut chars = word.chars();
+                            match chars.next() {
+                                Some(first) if i > 0 => first.to_uppercase().chain(chars).collect(),
+                                _ => word.clone(),
+                            }
+                        })
+                        .collect()
+                })
+            }),
+        }
+    }
+}
+
+/** 标识符开头和结尾的下划线（_private、__init__）原样保留，只转换中间的部分 */
+fn keep_underscores(token: &str, f: impl Fn(&str) -> String) -> String {
+    let name = token.trim_matches('_');
+    if name.is_empty() {
+        return token.into();
+    }
+    let start = token.len() - token.trim_start_matches('_').len();
+    let end = start + name.len();
+    format!("{}{}{}", &token[..start], f(name), &token[end..])
+}
+
+/** 对每个不含空白的片段应用 f */
+fn map_tokens(text: &str, f: impl Fn(&str) -> String) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut token_start = None;
+    for (index, c) in text.char_indices().chain(Some((text.len(), ' '))) {
+        match (token_start, c.is_whitespace()) {
+            (None, false) => token_start = Some(index),
+            (Some(start), true) => {
+                result.push_str(&f(&text[start..index]));
+                token_start = None;
+            }
+            _ => {}
+        }
+        if c.is_whitespace() && index < text.len() {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/** 对每个由字母、数字和下划线组成的标识符应用 f */
+fn map_identifiers(text: &str, f: impl Fn(&str) -> String) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(crate::is_word_char) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !crate::is_word_char(c))
+            .unwrap_or(rest.len());
+        result.push_str(&f(&rest[..end]));
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/** 把 fooBar、HTTPServer、foo_bar 这样的标识符拆成小写的单词 */
+fn split_words(token: &str) -> Vec<String> {
+    let chars: Vec<char> = token.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let previous = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1);
+        // 小写到大写之间，或连续大写的最后一个字母之前（HTTP|Server）拆开
+        let boundary = c.is_uppercase()
+            && (previous.is_some_and(|p| p.is_lowercase() || p.is_numeric())
+                || (previous.is_some_and(char::is_uppercase)
+                    && next.is_some_and(|n| n.is_lowercase())));
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_conversion_keeps_punctuation() {
+        let line = "let fooBar = getX(a, b_c);";
+        assert_eq!(Case::Snake.apply(line), "let foo_bar = get_x(a, b_c);");
+        assert_eq!(Case::Camel.apply(line), "let fooBar = getX(a, bC);");
+        assert_eq!(Case::Snake.apply("a.fooBar->bazQux"), "a.foo_bar->baz_qux");
+        assert_eq!(Case::Camel.apply("foo-bar"), "foo-bar");
+    }
+
+    #[test]
+    fn case_conversion_splits_words() {
+        assert_eq!(
+            Case::Snake.apply("HTTPServer parseURL2"),
+            "http_server parse_url2"
+        );
+        assert_eq!(
+            Case::Camel.apply("http_server  PARSE_URL"),
+            "httpServer  parseUrl"
+        );
+        assert_eq!(
+            Case::Snake.apply("_privateName __initValue__"),
+            "_private_name __init_value__"
+        );
+    }
+
+    #[test]
+    fn title_case() {
+        assert_eq!(
+            Case::Title.apply("hello wORLD foo-bar"),
+            "Hello World Foo-Bar"
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/** 最多保留的撤销步数 */
+const MAX_STEPS: usize = 1000;
+
+/** 把 range 中的行替换为 lines */
+pub struct Edit {
+    pub range: Range<usize>,
+    pub lines: Vec<String>,
+}
+
+/** 一步修改：edits 按发生的顺序排列，从后往前依次应用即可把内容恢复成修改前的样子 */
+struct Step {
+    edits: Vec<Edit>,
+    /** 修改前和修改后的光标位置 */
+    before: (usize, usize),
+    after: (usize, usize),
+}
+
+/**
+ * 撤销历史。EditorRows 在每次修改时记录反向的修改，
+ * 这里把一次或连续几次修改记录的内容合并为一步
+ */
+#[derive(Default)]
+pub struct History {
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    /** 有还没记录的修改时为修改开始前的光标位置 */
+    pending: Option<(usize, usize)>,
+}
+
+impl History {
+    /** 丢弃所有历史 */
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /** 内容被修改了，cursor 为修改前的光标位置，连续的修改只保留第一次的位置 */
+    pub fn touch(&mut self, cursor: (usize, usize)) {
+        self.pending.get_or_insert(cursor);
+    }
+
+    /** 把 edits（上次记录以来的反向修改）记为一步 */
+    pub fn commit(&mut self, edits: Vec<Edit>, cursor: (usize, usize)) {
+        let before = self.pending.take().unwrap_or(cursor);
+        if edits.is_empty() {
+            return;
+        }
+        self.undo.push_back(Step {
+            edits,
+            before,
+            after: cursor,
+        });
+        if self.undo.len() > MAX_STEPS {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    /**
+     * 撤销一步：apply 从后往前应用修改并返回它们的反向修改，用于重做。
+     * 返回撤销后的光标位置，没有可以撤销的修改时返回 None
+     */
+    pub fn undo(&mut self, apply: impl FnOnce(Vec<Edit>) -> Vec<Edit>) -> Option<(usize, usize)> {
+        let step = self.undo.pop_back()?;
+        let cursor = step.before;
+        self.redo.push(Step {
+            edits: apply(step.edits),
+            ..step
+        });
+        Some(cursor)
+    }
+
+    /** 与 undo 相同，重做上一次撤销的修改 */
+    pub fn redo(&mut self, apply: impl FnOnce(Vec<Edit>) -> Vec<Edit>) -> Option<(usize, usize)> {
+        let step = self.redo.pop()?;
+        let cursor = step.after;
+        self.undo.push_back(Step {
+            edits: apply(step.edits),
+            ..step
+        });
+        Some(cursor)
+    }
+}